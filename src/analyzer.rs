@@ -2,77 +2,99 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
-use gio;
 use gtk::prelude::*;
 use gtk::{Window, Inhibit, WindowType};
 use humansize::{FileSize, file_size_opts as options};
-use relm::{connect, Relm, Update, Widget};
+use relm::{connect, Channel, Relm, Update, Widget};
 use relm_derive::Msg;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 use std::sync::{Arc, Weak, Mutex};
+use std::sync::mpsc::channel;
+use std::thread;
+use std::time::UNIX_EPOCH;
+use super::dialogs;
 use super::dir_walker;
+use super::duplicate_list;
+use super::duplicates;
+use super::error_list;
+use super::hierarchy_view;
+use super::launcher;
+use super::list_format::{self, CellDataFunc};
+use super::navigation;
+use super::removal;
+use super::ring_chart;
+use super::scan_diff;
+use super::snapshot;
+use super::top_entries;
+use super::top_view;
+use super::treemap;
+use super::type_view;
+use super::watcher;
 
-static FOLDER_ICON: &str = "folder";
-static ERROR_ICON: &str = "dialog-error";
+/// How many entries the lists of the largest files and directories keep.
+const TOP_ENTRY_COUNT: usize = 100;
 
-type CellDataFunc = Box<dyn Fn(&gtk::TreeViewColumn, &gtk::CellRenderer, &gtk::TreeModel, &gtk::TreeIter) + 'static>;
+fn format_signed_size(delta: i64) -> String {
+    let sign = if delta < 0 { "−" } else { "+" };
+    format!("{}{}", sign, delta.unsigned_abs().file_size(options::CONVENTIONAL).unwrap())
+}
 
-fn fill_list_store(store: &gtk::ListStore, dir: &Mutex<dir_walker::Directory>) {
-    let current_directory = dir.lock().unwrap();
-    let current_directory_size = current_directory.get_size();
-    for sub in current_directory.get_subdirectories() {
-        let subdir = sub.lock().unwrap();
-        if subdir.has_error() {
-            store.insert_with_values(None, &[0, 1, 2, 3], &[&ERROR_ICON, &subdir.get_name(), &current_directory_size, &subdir.get_size()]);
+fn describe_change(change: &scan_diff::EntryChange) -> String {
+    let delta = format_signed_size(change.get_delta());
+    match change.change {
+        scan_diff::Change::Added => format!("New ({})", delta),
+        scan_diff::Change::Removed => format!("Removed ({})", delta),
+        scan_diff::Change::Unchanged => String::new(),
+        scan_diff::Change::Grown | scan_diff::Change::Shrunk => match change.get_delta_percent() {
+            Some(percentage) => format!("{} ({:+.1}%)", delta, percentage),
+            None => delta
         }
-        else {
-            store.insert_with_values(None, &[0, 1, 2, 3], &[&FOLDER_ICON, &subdir.get_name(), &current_directory_size, &subdir.get_size()]);
-        }
-    }
-    for file in current_directory.get_files() {
-        store.insert_with_values(None, &[0, 1, 2, 3], &[&file.get_mime(), &file.get_name(), &current_directory_size, &file.get_size()]);
     }
 }
 
-fn add_column<R: IsA<gtk::CellRenderer>>(tree: &gtk::TreeView, id: i32, title: &str, data_func: Option<CellDataFunc>,
-                                            is_sortable: bool, cell: R)
-{
-    let column = gtk::TreeViewColumn::new();
-
-    column.pack_start(&cell, true);
-    column.set_title(title);
-
-    if is_sortable {
-        column.set_clickable(true);
-        column.set_sort_indicator(true);
-        column.set_sort_column_id(id);
+/// The values of the delta and change columns for a row, which are left empty when not comparing scans.
+fn get_change_values(change: Option<&scan_diff::EntryChange>) -> (i64, String) {
+    match change {
+        Some(change) => (change.get_delta(), describe_change(change)),
+        None => (0, String::new())
     }
+}
 
-    if data_func.is_some() {
-        gtk::TreeViewColumnExt::set_cell_data_func(&column, &cell, data_func);
+fn fill_list_store(store: &gtk::ListStore, dir: &Mutex<dir_walker::Directory>, comparison: Option<&scan_diff::DirectoryComparison>,
+                   size_mode: dir_walker::SizeMode) {
+    let columns = [0, 1, 2, 3, 4, 5];
+    let current_directory = dir.lock().unwrap();
+    let current_directory_size = current_directory.get_size_for(size_mode);
+    for (index, sub) in current_directory.get_subdirectories().iter().enumerate() {
+        let subdir = sub.lock().unwrap();
+        let icon = list_format::get_directory_icon(&subdir);
+        let name = list_format::get_display_name(&subdir.get_name(), subdir.get_link_target());
+        let (delta, change) = get_change_values(comparison.map(|comparison| &comparison.subdirectories[index]));
+        store.insert_with_values(None, &columns, &[&icon, &name, &current_directory_size, &subdir.get_size_for(size_mode),
+                                                   &delta, &change]);
+    }
+    for (index, file) in current_directory.get_files().iter().enumerate() {
+        let icon = list_format::get_file_icon(file);
+        let name = list_format::get_display_name(&file.get_name(), file.get_link_target());
+        let (delta, change) = get_change_values(comparison.map(|comparison| &comparison.files[index]));
+        store.insert_with_values(None, &columns, &[&icon, &name, &current_directory_size, &file.get_size_for(size_mode),
+                                                   &delta, &change]);
     }
-    else {
-        column.add_attribute(&cell, "text", id);
+    // Entries that are gone are listed last, so row indices still line up with the directory's own lists.
+    for removed in comparison.map(|comparison| comparison.removed.as_slice()).unwrap_or_default() {
+        let icon = removed.mime.as_deref().unwrap_or(list_format::FOLDER_ICON);
+        let name = removed.name.to_string_lossy();
+        let change = scan_diff::EntryChange { change: scan_diff::Change::Removed, old_size: removed.size, new_size: 0 };
+        let (delta, change) = get_change_values(Some(&change));
+        store.insert_with_values(None, &columns, &[&icon, &name.as_ref(), &current_directory_size, &0u64, &delta, &change]);
     }
-    tree.append_column(&column);
 }
 
-fn create_analyzer_columns(file_list: &gtk::TreeView) {
-    let icon_data_func: CellDataFunc = Box::new(|_, render, model, iter| {
-        let cell = render.clone().downcast::<gtk::CellRendererPixbuf>().expect("Expected renderer to be CellRenderText");
-        let model_val = model.get_value(&iter, 0);
-        let icon_name = model_val.get::<&str>().expect("Couldn't get icon name").expect("Couldn't get icon name");
-
-        if icon_name == FOLDER_ICON || icon_name == ERROR_ICON {
-            cell.set_property_icon_name(Some(icon_name));
-        }
-        else {
-            let icon = gio::content_type_get_icon(icon_name);
-            cell.set_property_gicon(icon.as_ref());
-        }
-    });
-
-    add_column(&file_list, 0, "", Some(icon_data_func), false, gtk::CellRendererPixbuf::new());
-    add_column(&file_list, 1, "Name", None, true, gtk::CellRendererText::new());
+/// Adds the list's columns and returns the change column, which is only shown while comparing scans.
+fn create_analyzer_columns(file_list: &gtk::TreeView) -> gtk::TreeViewColumn {
+    list_format::add_icon_column(&file_list, 0);
+    list_format::add_column(&file_list, 1, "Name", None, true, gtk::CellRendererText::new());
 
     let percentage_data_func: CellDataFunc = Box::new(|_, render, model, iter| {
         let cell = render.clone().downcast::<gtk::CellRendererText>().expect("Expected renderer to be CellRenderText");
@@ -87,29 +109,71 @@ fn create_analyzer_columns(file_list: &gtk::TreeView) {
         let formatted = format!("{:.0}%", percentage);
         cell.set_property_text(Some(&formatted));
     });
-    add_column(&file_list, 2, "%", Some(percentage_data_func), false, gtk::CellRendererText::new());
+    list_format::add_column(&file_list, 2, "%", Some(percentage_data_func), false, gtk::CellRendererText::new());
 
-    let size_data_func: CellDataFunc = Box::new(|_, render, model, iter| {
-        let cell = render.clone().downcast::<gtk::CellRendererText>().expect("Expected renderer to be CellRenderText");
-        let val = model.get_value(&iter, 3).get::<u64>()
-            .expect("Couldn't get size value from tree model")
-            .expect("Couldn't get size value from tree model");
-        let formatted_size = val.file_size(options::CONVENTIONAL).unwrap();
-        cell.set_property_text(Some(&formatted_size));
-    });
-    add_column(&file_list, 3, "Size", Some(size_data_func), true, gtk::CellRendererText::new());
+    list_format::add_size_column(&file_list, 3, "Size");
+
+    let change_column = list_format::add_column(&file_list, 5, "Change", None, false, gtk::CellRendererText::new());
+    change_column.set_clickable(true);
+    change_column.set_sort_indicator(true);
+    change_column.set_sort_column_id(4);
+    change_column.set_visible(false);
+    change_column
 }
 
 pub struct AnalyzerModel {
     root: Arc<Mutex<dir_walker::Directory>>,
-    current: Weak<Mutex<dir_walker::Directory>>
+    current: Weak<Mutex<dir_walker::Directory>>,
+    size_mode: dir_walker::SizeMode,
+    scan_info: dir_walker::ScanInfo,
+    /// The root of an earlier scan that the current one is being compared with.
+    baseline: Option<Arc<Mutex<dir_walker::Directory>>>,
+    history: navigation::History,
+    /// Set while the tree is kept up to date with changes on disk.
+    watcher: Option<watcher::Watcher>,
+    /// Set while the largest entries are looked for in the background.
+    finding_top_entries: bool,
+    /// Set when the tree changed again meanwhile, so they have to be looked for once more.
+    top_entries_outdated: bool,
+    relm: Relm<AnalyzerWindow>
 }
 
 #[derive(Msg)]
 pub enum AnalyzerMsg {
     Quit,
     RowActivated(gtk::TreePath),
-    Up
+    TreeRowActivated(gtk::TreePath),
+    ShowTree(bool),
+    PageChanged,
+    Up,
+    Back,
+    Forward,
+    NavigateTo(navigation::Location),
+    ShowAllocatedSize(bool),
+    Save,
+    Compare,
+    TreemapClicked((f64, f64)),
+    RingChartClicked((f64, f64)),
+    GotBaseline(Result<(Arc<Mutex<dir_walker::Directory>>, dir_walker::ScanInfo), snapshot::SnapshotError>),
+    Remove(removal::Method),
+    Removed(Vec<removal::Outcome>),
+    ShowContextMenu(gdk::EventButton),
+    Open,
+    OpenContainingFolder,
+    CopyPath,
+    OpenTerminal,
+    Rescan,
+    Rescanned((Arc<Mutex<dir_walker::Directory>>, Arc<Mutex<dir_walker::Directory>>)),
+    Watch(bool),
+    GotWatchEvents(Vec<watcher::WatchEvent>),
+    FindDuplicates,
+    GotDuplicates(Vec<duplicates::DuplicateSet>),
+    DuplicateRowActivated(gtk::TreePath),
+    OpenDuplicate,
+    RemoveDuplicate(removal::Method),
+    GotTopEntries(top_entries::TopEntries),
+    TopFileActivated(gtk::TreePath),
+    TopDirectoryActivated(gtk::TreePath)
 }
 
 pub struct AnalyzerWindow {
@@ -117,10 +181,232 @@ pub struct AnalyzerWindow {
     window: Window,
     list_store: gtk::ListStore,
     sort_store: gtk::TreeModelSort,
-    header_bar: gtk::HeaderBar
+    file_list: gtk::TreeView,
+    context_menu: gtk::Menu,
+    header_bar: gtk::HeaderBar,
+    change_column: gtk::TreeViewColumn,
+    treemap: treemap::Treemap,
+    ring_chart: ring_chart::RingChart,
+    hierarchy: hierarchy_view::HierarchyView,
+    list_stack: gtk::Stack,
+    breadcrumbs: gtk::Box,
+    back_button: gtk::Button,
+    forward_button: gtk::Button,
+    up_button: gtk::Button,
+    rescan_button: gtk::Button,
+    rescan_spinner: gtk::Spinner,
+    watch_button: gtk::ToggleButton,
+    duplicate_list: duplicate_list::DuplicateList,
+    duplicates_label: gtk::Label,
+    find_duplicates_button: gtk::Button,
+    type_view: Rc<type_view::TypeView>,
+    top_view: top_view::TopView,
+    notebook: gtk::Notebook,
+    trash_button: gtk::Button,
+    delete_button: gtk::Button,
+    error_list: error_list::ErrorList,
+    errors_label: gtk::Label,
+    watch_warning: gtk::InfoBar,
+    watch_warning_label: gtk::Label
+}
+
+/// Alt+Left/Right and Backspace move through the history, Alt+Up goes to the parent directory.
+/// F5 rescans the current directory. Keys without Alt are left alone while `editing` text.
+fn get_shortcut(event: &gdk::EventKey, editing: bool) -> Option<AnalyzerMsg> {
+    use gdk::keys::constants as keys;
+    let alt = event.get_state().contains(gdk::ModifierType::MOD1_MASK);
+    match event.get_keyval() {
+        keys::Left if alt => Some(AnalyzerMsg::Back),
+        keys::Right if alt => Some(AnalyzerMsg::Forward),
+        keys::Up if alt => Some(AnalyzerMsg::Up),
+        keys::BackSpace if !editing => Some(AnalyzerMsg::Back),
+        keys::F5 if !editing => Some(AnalyzerMsg::Rescan),
+        _ => None
+    }
+}
+
+/// Delete moves the selected entries to the trash, and Shift+Delete deletes them. Only handled by the file list,
+/// so nothing is removed unless its selection is the one in front of the user.
+fn get_removal_shortcut(event: &gdk::EventKey) -> Option<AnalyzerMsg> {
+    use gdk::keys::constants as keys;
+    let shift = event.get_state().contains(gdk::ModifierType::SHIFT_MASK);
+    match event.get_keyval() {
+        keys::Delete if shift => Some(AnalyzerMsg::Remove(removal::Method::Delete)),
+        keys::Delete => Some(AnalyzerMsg::Remove(removal::Method::Trash)),
+        _ => None
+    }
 }
 
 impl AnalyzerWindow {
+    /// Lists the contents of `dir`, compared with the matching directory of the earlier scan if there is one.
+    fn show_directory(&mut self, dir: &Arc<Mutex<dir_walker::Directory>>) {
+        let comparison = self.model.baseline.as_ref().map(|baseline| {
+            let matching = navigation::Location::of(dir).find(baseline);
+            let matching_dir = matching.as_ref().map(|matching| matching.lock().unwrap());
+            scan_diff::compare_directory(matching_dir.as_deref(), &dir.lock().unwrap(), self.model.size_mode)
+        });
+
+        self.list_store.clear();
+        fill_list_store(&self.list_store, dir, comparison.as_ref(), self.model.size_mode);
+        self.header_bar.set_subtitle(Some(&dir.lock().unwrap().get_display_path()));
+        let root_size = self.model.root.lock().unwrap().get_size_for(self.model.size_mode);
+        self.hierarchy.set_directory(dir, root_size, self.model.size_mode);
+        self.treemap.set_directory(dir, self.model.size_mode);
+        self.ring_chart.set_directory(dir, self.model.size_mode);
+        self.type_view.set_directory(dir, self.model.size_mode);
+        self.model.current = Arc::downgrade(dir);
+        self.update_navigation(dir);
+    }
+
+    /// Rebuilds the breadcrumbs for `dir` and enables only the navigation buttons that lead somewhere.
+    fn update_navigation(&self, dir: &Arc<Mutex<dir_walker::Directory>>) {
+        for child in self.breadcrumbs.get_children() {
+            self.breadcrumbs.remove(&child);
+        }
+
+        let ancestors = navigation::Location::of(dir).get_ancestors(&self.model.root);
+        for (index, ancestor) in ancestors.iter().enumerate() {
+            let name = if index == 0 {
+                ancestor.lock().unwrap().get_display_path().into_owned()
+            }
+            else {
+                ancestor.lock().unwrap().get_name().into_owned()
+            };
+
+            if index > 0 {
+                self.breadcrumbs.add(&gtk::Label::new(Some("›")));
+            }
+            if index + 1 == ancestors.len() {
+                let label = gtk::Label::new(None);
+                label.set_markup(&format!("<b>{}</b>", glib::markup_escape_text(&name)));
+                self.breadcrumbs.add(&label);
+            }
+            else {
+                let button = gtk::Button::with_label(&name);
+                button.set_relief(gtk::ReliefStyle::None);
+                let location = navigation::Location::of(ancestor);
+                connect!(self.model.relm, button, connect_clicked(_), AnalyzerMsg::NavigateTo(location.clone()));
+                self.breadcrumbs.add(&button);
+            }
+        }
+        self.breadcrumbs.show_all();
+
+        self.back_button.set_sensitive(self.model.history.can_go_back());
+        self.forward_button.set_sensitive(self.model.history.can_go_forward());
+        self.up_button.set_sensitive(ancestors.len() > 1);
+    }
+
+    /// Shows `dir` and records the directory being left in the history.
+    fn navigate_to(&mut self, dir: &Arc<Mutex<dir_walker::Directory>>) {
+        if let Some(current) = self.model.current.upgrade() {
+            self.model.history.push(navigation::Location::of(&current));
+        }
+        self.show_directory(dir);
+    }
+
+    /// Goes to the directory containing `path` in the Files tab and selects its row.
+    fn show_entry(&mut self, path: Option<PathBuf>) {
+        let path = match path {
+            Some(path) => path,
+            None => return
+        };
+        // The tree may have changed since the lists were filled, so the directory is looked up again.
+        let parent = match path.parent().and_then(|parent| dir_walker::find_directory(&self.model.root, parent)) {
+            Some(parent) => parent,
+            None => return
+        };
+        self.navigate_to(&parent);
+        self.notebook.set_current_page(Some(0));
+
+        let index = {
+            let unwrapped_parent = parent.lock().unwrap();
+            let subdirs = unwrapped_parent.get_subdirectories();
+            subdirs.iter().position(|subdir| subdir.lock().unwrap().get_path() == path)
+                   .or_else(|| unwrapped_parent.get_files().iter()
+                                               .position(|file| Some(file.get_os_name()) == path.file_name())
+                                               .map(|index| index + subdirs.len()))
+        };
+        let sorted_path = index.and_then(|index| {
+            self.sort_store.convert_child_path_to_path(&gtk::TreePath::from_indicesv(&[index as i32]))
+        });
+        if let Some(sorted_path) = sorted_path {
+            self.file_list.get_selection().unselect_all();
+            self.file_list.get_selection().select_path(&sorted_path);
+            self.file_list.scroll_to_cell(Some(&sorted_path), None::<&gtk::TreeViewColumn>, false, 0.0, 0.0);
+        }
+    }
+
+    /// Finds the largest files and directories again in the background after the tree or the size mode changed.
+    /// Only one search runs at a time, changes made while it does start another one once it's done.
+    fn update_top_entries(&mut self) {
+        if self.model.finding_top_entries {
+            self.model.top_entries_outdated = true;
+            return;
+        }
+        self.model.finding_top_entries = true;
+        self.model.top_entries_outdated = false;
+
+        let stream = self.model.relm.stream().clone();
+        let (_, sender) = Channel::new(move |entries| {
+            stream.emit(AnalyzerMsg::GotTopEntries(entries));
+        });
+
+        let root = self.model.root.clone();
+        let size_mode = self.model.size_mode;
+        thread::spawn(move || {
+            sender.send(top_entries::find_top_entries(&root, TOP_ENTRY_COUNT, size_mode)).expect("Couldn't send message");
+        });
+    }
+
+    /// Shows the largest entries even if the tree changed in the meantime, so a steady stream of changes
+    /// doesn't keep the list from ever being updated.
+    fn on_top_entries_found(&mut self, entries: top_entries::TopEntries) {
+        self.top_view.set_entries(&entries);
+        self.model.finding_top_entries = false;
+        if self.model.top_entries_outdated {
+            self.update_top_entries();
+        }
+    }
+
+    fn on_history(&mut self, forward: bool) {
+        let current = navigation::Location::of(&self.model.current.upgrade().expect("Current dir shouldn't be none"));
+        let target = if forward {
+            self.model.history.go_forward(current)
+        }
+        else {
+            self.model.history.go_back(current)
+        };
+
+        // History entries are locations rather than references, so they still lead somewhere sensible if
+        // the directory they were recorded for has been replaced or removed since.
+        if let Some(target) = target {
+            let dir = target.resolve(&self.model.root);
+            self.show_directory(&dir);
+        }
+    }
+
+    /// Navigates into `dir`, or explains why it can't be shown if it wasn't read.
+    fn open_directory(&mut self, dir: &Arc<Mutex<dir_walker::Directory>>) {
+        let error = dir.lock().unwrap().get_error().clone();
+        if let Some(e) = error {
+            let msg = format!("Could not read directory contents\n\n{}", e);
+            let message_box = gtk::MessageDialog::new(Some(&self.window), gtk::DialogFlags::MODAL, gtk::MessageType::Error,
+                                                      gtk::ButtonsType::Ok, &msg);
+            message_box.run();
+            message_box.hide();
+        }
+        else if dir.lock().unwrap().is_mount_point() {
+            let msg = format!("{} is on another file system and was not scanned", dir.lock().unwrap().get_display_path());
+            let message_box = gtk::MessageDialog::new(Some(&self.window), gtk::DialogFlags::MODAL, gtk::MessageType::Info,
+                                                      gtk::ButtonsType::Ok, &msg);
+            message_box.run();
+            message_box.hide();
+        }
+        else {
+            self.navigate_to(dir);
+        }
+    }
+
     fn on_row_activated(&mut self, path: gtk::TreePath) {
         let current = self.model.current.upgrade().expect("Shouldn't be none");
         let current_unlocked = current.lock().unwrap();
@@ -130,33 +416,444 @@ impl AnalyzerWindow {
             .expect("Sorted path does not correspond to real path").get_indices();
         if indices.len() > 0 {
             let index = indices[0] as usize;
-            if index < files_start_index { // only want directories
-                let new_dir = &subdirs[index];
-                if new_dir.lock().unwrap().has_error() {
-                    let msg = format!("Could not read directory contents");
-                    let message_box = gtk::MessageDialog::new(Some(&self.window), gtk::DialogFlags::MODAL, gtk::MessageType::Error,
-                                                              gtk::ButtonsType::Ok, &msg);
-                    message_box.run();
-                    message_box.hide();
-                }
-                else {
-                    self.list_store.clear();
-                    fill_list_store(&self.list_store, &new_dir);
-                    self.header_bar.set_subtitle(Some(new_dir.lock().unwrap().get_path()));
-                    self.model.current = Arc::downgrade(&new_dir);
-                }
+            if index < files_start_index {
+                let new_dir = subdirs[index].clone();
+                drop(current_unlocked);
+                self.open_directory(&new_dir);
+            }
+            else if let Some(file) = current_unlocked.get_files().get(index - files_start_index) {
+                let file_path = current_unlocked.get_file_path(file);
+                drop(current_unlocked);
+                self.launch(launcher::open(self.window.upcast_ref(), &file_path), &file_path);
             }
         }
     }
 
+    /// Shows an error if launching something for `path` failed.
+    fn launch(&self, result: Result<(), glib::Error>, path: &Path) {
+        if let Err(e) = result {
+            let msg = format!("Could not open {}\n\n{}", path.display(), e);
+            let message_box = gtk::MessageDialog::new(Some(&self.window), gtk::DialogFlags::MODAL, gtk::MessageType::Error,
+                                                      gtk::ButtonsType::Ok, &msg);
+            message_box.run();
+            message_box.hide();
+        }
+    }
+
+    /// The path of the entry the context menu was opened for, and whether it's a directory.
+    fn get_context_entry(&self) -> Option<(PathBuf, bool)> {
+        self.get_selected_targets().into_iter().next().map(|target| {
+            let is_directory = matches!(target.entry, removal::Entry::Directory(_));
+            (target.path, is_directory)
+        })
+    }
+
+    fn on_show_context_menu(&self, event: gdk::EventButton) {
+        let (x, y) = event.get_position();
+        let selection = self.file_list.get_selection();
+        if let Some((Some(path), _, _, _)) = self.file_list.get_path_at_pos(x as i32, y as i32) {
+            // Right-clicking outside the selection selects just the row that was clicked, like in file managers.
+            if !selection.path_is_selected(&path) {
+                selection.unselect_all();
+                selection.select_path(&path);
+            }
+        }
+
+        if self.get_context_entry().is_some() {
+            self.context_menu.popup_easy(event.get_button(), event.get_time());
+        }
+    }
+
+    fn on_context_action(&self, event: AnalyzerMsg) {
+        let (path, is_directory) = match self.get_context_entry() {
+            Some(entry) => entry,
+            None => return
+        };
+        let parent = path.parent().unwrap_or(&path).to_path_buf();
+        let widget = self.window.upcast_ref();
+        match event {
+            AnalyzerMsg::Open => self.launch(launcher::open(widget, &path), &path),
+            AnalyzerMsg::OpenContainingFolder => self.launch(launcher::open(widget, &parent), &parent),
+            AnalyzerMsg::CopyPath => {
+                gtk::Clipboard::get(&gdk::SELECTION_CLIPBOARD).set_text(&path.to_string_lossy());
+            },
+            AnalyzerMsg::OpenTerminal => {
+                let dir = if is_directory { &path } else { &parent };
+                self.launch(launcher::open_terminal(widget, dir), dir);
+            },
+            _ => ()
+        }
+    }
+
     fn on_up_clicked(&mut self) {
         let current = self.model.current.upgrade().expect("Current dir shouldn't be none");
         let parent_ptr = current.lock().unwrap().get_parent();
         if let Some(parent) = parent_ptr.upgrade() {
-            self.list_store.clear();
-            fill_list_store(&self.list_store, &parent);
-            self.header_bar.set_subtitle(Some(parent.lock().unwrap().get_path()));
-            self.model.current = Arc::downgrade(&parent);
+            self.navigate_to(&parent);
+        }
+    }
+
+    fn on_save(&self) {
+        if let Some(file_path) = dialogs::choose_file(&self.window, "Save scan", Some(&format!("scan.{}", snapshot::BINARY_EXTENSION))) {
+            if let Err(e) = snapshot::save(&file_path, &self.model.root, &self.model.scan_info) {
+                let msg = format!("Could not save the scan\n\n{}", e);
+                let message_box = gtk::MessageDialog::new(Some(&self.window), gtk::DialogFlags::MODAL, gtk::MessageType::Error,
+                                                          gtk::ButtonsType::Ok, &msg);
+                message_box.run();
+                message_box.hide();
+            }
+        }
+    }
+
+    fn on_show_allocated_size(&mut self, show_allocated: bool) {
+        self.model.size_mode = if show_allocated {
+            dir_walker::SizeMode::Allocated
+        }
+        else {
+            dir_walker::SizeMode::Apparent
+        };
+
+        let current = self.model.current.upgrade().expect("Current dir shouldn't be none");
+        self.show_directory(&current);
+        self.update_top_entries();
+    }
+
+    fn on_compare(&self) {
+        if let Some(file_path) = dialogs::choose_file(&self.window, "Compare with an earlier scan", None) {
+            let stream = self.model.relm.stream().clone();
+            let (_, sender) = Channel::new(move |result| {
+                stream.emit(AnalyzerMsg::GotBaseline(result));
+            });
+
+            thread::spawn(move || {
+                sender.send(snapshot::load(&file_path)).expect("Couldn't send message");
+            });
+        }
+    }
+
+    fn on_baseline_loaded(&mut self, result: Result<(Arc<Mutex<dir_walker::Directory>>, dir_walker::ScanInfo), snapshot::SnapshotError>) {
+        let msg = match result {
+            Ok((_, ref info)) if info.paths != self.model.scan_info.paths => {
+                let describe = |paths: &[std::path::PathBuf]| paths.iter().map(|path| path.display().to_string()).collect::<Vec<_>>().join(", ");
+                format!("The saved scan is of {}, but this one is of {}", describe(&info.paths), describe(&self.model.scan_info.paths))
+            },
+            Ok((baseline, info)) => {
+                let seconds = info.scanned_at.duration_since(UNIX_EPOCH).map(|duration| duration.as_secs()).unwrap_or(0);
+                let scanned_at = glib::DateTime::from_unix_local(seconds as i64).format("%c").map(|date| date.to_string()).unwrap_or_default();
+                self.header_bar.set_title(Some(&format!("Compared with scan from {}", scanned_at)));
+                self.change_column.set_visible(true);
+                self.sort_store.set_sort_column_id(gtk::SortColumn::Index(4), gtk::SortType::Descending);
+                self.model.baseline = Some(baseline);
+
+                let current = self.model.current.upgrade().expect("Current dir shouldn't be none");
+                self.show_directory(&current);
+                return;
+            },
+            Err(e) => format!("Could not open saved scan\n\n{}", e)
+        };
+
+        let message_box = gtk::MessageDialog::new(Some(&self.window), gtk::DialogFlags::MODAL, gtk::MessageType::Error,
+                                                  gtk::ButtonsType::Ok, &msg);
+        message_box.run();
+        message_box.hide();
+    }
+
+    /// Scans the current directory again in the background. Only one rescan runs at a time.
+    fn on_rescan(&mut self) {
+        let current = self.model.current.upgrade().expect("Current dir shouldn't be none");
+        if !self.rescan_button.get_sensitive() || current.lock().unwrap().is_mount_point() {
+            return;
+        }
+        self.rescan_button.set_sensitive(false);
+        self.rescan_spinner.start();
+
+        let stream = self.model.relm.stream().clone();
+        let (_, sender) = Channel::new(move |dirs| {
+            stream.emit(AnalyzerMsg::Rescanned(dirs));
+        });
+
+        let is_root = Arc::ptr_eq(&current, &self.model.root);
+        let root = self.model.root.clone();
+        let paths = self.model.scan_info.paths.clone();
+        let options = self.model.scan_info.options.clone();
+        thread::spawn(move || {
+            // Nothing cancels a rescan, but the walker wants a channel to check.
+            let (_cancel_sender, cancel_receiver) = channel();
+            let progress = dir_walker::ScanProgress::new();
+            let new_dir = if is_root {
+                dir_walker::read_dirs(&paths, cancel_receiver, &options, &progress)
+            }
+            else {
+                dir_walker::rescan_dir(&root, &current, &paths, cancel_receiver, &options, &progress)
+            };
+            sender.send((current, new_dir)).expect("Couldn't send message");
+        });
+    }
+
+    /// Swaps a rescanned directory into the tree and shows the same location as before.
+    fn on_rescanned(&mut self, old_dir: Arc<Mutex<dir_walker::Directory>>, new_dir: Arc<Mutex<dir_walker::Directory>>) {
+        self.rescan_button.set_sensitive(true);
+        self.rescan_spinner.stop();
+
+        let current = navigation::Location::of(&self.model.current.upgrade().expect("Current dir shouldn't be none"));
+        if Arc::ptr_eq(&old_dir, &self.model.root) {
+            self.model.root = new_dir;
+            self.model.scan_info.scanned_at = std::time::SystemTime::now();
+        }
+        else if !dir_walker::replace_subdirectory(&old_dir, &new_dir) {
+            // The directory was removed from the tree while it was being scanned.
+            return;
+        }
+
+        let dir = current.resolve(&self.model.root);
+        self.show_directory(&dir);
+        self.update_top_entries();
+        self.update_errors();
+
+        // The sets found so far point into the tree that was just replaced.
+        if self.duplicate_list.get_set_count() > 0 {
+            self.duplicate_list.set_duplicates(Vec::new());
+            self.duplicates_label.set_text("Rescanned, find duplicates again to update the list");
+        }
+    }
+
+    /// Lists the errors anywhere in the tree, and their number in the tab title.
+    fn update_errors(&self) {
+        let errors = dir_walker::collect_errors(&self.model.root);
+        self.errors_label.set_text(&format!("Errors ({})", errors.len()));
+        self.error_list.set_errors(&errors);
+    }
+
+    fn on_watch(&mut self, watch: bool) {
+        if !watch {
+            self.model.watcher = None;
+            self.watch_warning.hide();
+            return;
+        }
+        if self.model.watcher.is_some() {
+            return;
+        }
+
+        let stream = self.model.relm.stream().clone();
+        let (_, sender) = Channel::new(move |events| {
+            stream.emit(AnalyzerMsg::GotWatchEvents(events));
+        });
+        match watcher::Watcher::new(&self.model.root, &self.model.scan_info.options, sender) {
+            Ok(watcher) => self.model.watcher = Some(watcher),
+            Err(e) => {
+                let msg = format!("Could not watch for changes\n\n{}", e);
+                let message_box = gtk::MessageDialog::new(Some(&self.window), gtk::DialogFlags::MODAL, gtk::MessageType::Error,
+                                                          gtk::ButtonsType::Ok, &msg);
+                message_box.run();
+                message_box.hide();
+                self.watch_button.set_active(false);
+            }
+        }
+    }
+
+    /// Patches the tree with changes seen on disk, and refreshes the view if they show up in it.
+    fn on_watch_events(&mut self, events: Vec<watcher::WatchEvent>) {
+        // Events from a watcher that has just been turned off are dropped.
+        if self.model.watcher.is_none() {
+            return;
+        }
+
+        let current = self.model.current.upgrade().expect("Current dir shouldn't be none");
+        let current_path = current.lock().unwrap().get_path().to_path_buf();
+        let mut refresh = false;
+        let mut changed = false;
+        let mut errors = Vec::new();
+        for event in events {
+            match event {
+                watcher::WatchEvent::Error(e) => errors.push(e),
+                event => {
+                    refresh |= matches!(event.get_directory(), Some(dir) if dir.starts_with(&current_path));
+                    changed = true;
+                    watcher::apply(&self.model.root, event);
+                }
+            }
+        }
+
+        if refresh {
+            let dir = navigation::Location::of(&current).resolve(&self.model.root);
+            self.show_directory(&dir);
+        }
+        if changed {
+            self.update_top_entries();
+        }
+        // A burst of errors is shown at once in the bar above the tabs, without stopping the window.
+        if !errors.is_empty() {
+            errors.dedup();
+            self.watch_warning_label.set_text(&errors.join("\n\n"));
+            self.watch_warning.show();
+        }
+    }
+
+    fn on_find_duplicates(&self) {
+        self.find_duplicates_button.set_sensitive(false);
+        self.duplicates_label.set_text("Comparing files...");
+
+        let stream = self.model.relm.stream().clone();
+        let (_, sender) = Channel::new(move |sets| {
+            stream.emit(AnalyzerMsg::GotDuplicates(sets));
+        });
+
+        let root = self.model.root.clone();
+        thread::spawn(move || {
+            sender.send(duplicates::find_duplicates(&root)).expect("Couldn't send message");
+        });
+    }
+
+    fn update_duplicates_label(&self) {
+        let wasted = self.duplicate_list.get_wasted_size().file_size(options::CONVENTIONAL).unwrap();
+        self.duplicates_label.set_text(&format!("{} sets of duplicates, {} wasted", self.duplicate_list.get_set_count(), wasted));
+    }
+
+    fn on_duplicates_found(&self, sets: Vec<duplicates::DuplicateSet>) {
+        self.duplicate_list.set_duplicates(sets);
+        self.update_duplicates_label();
+        self.find_duplicates_button.set_sensitive(true);
+    }
+
+    fn on_open_duplicate(&self, file: Option<duplicates::DuplicateFile>) {
+        if let Some(file) = file {
+            self.launch(launcher::open(self.window.upcast_ref(), &file.path), &file.path);
+        }
+    }
+
+    fn on_remove_duplicate(&self, method: removal::Method) {
+        if let Some(file) = self.duplicate_list.get_selected_file() {
+            let size = file.parent.lock().unwrap().get_files().iter()
+                           .find(|other| other.get_os_name() == file.name)
+                           .map_or(0, |other| other.get_size_for(self.model.size_mode));
+            let target = removal::Target {
+                parent: file.parent,
+                entry: removal::Entry::File(file.name),
+                path: file.path,
+                size
+            };
+            self.remove(vec![target], method);
+        }
+    }
+
+    /// The entries selected in the list. Rows for entries that only exist in an earlier scan are left out.
+    fn get_selected_targets(&self) -> Vec<removal::Target> {
+        let current = self.model.current.upgrade().expect("Current dir shouldn't be none");
+        let current_unlocked = current.lock().unwrap();
+        let subdirs = current_unlocked.get_subdirectories();
+        let files = current_unlocked.get_files();
+        let (paths, _) = self.file_list.get_selection().get_selected_rows();
+        paths.iter().filter_map(|path| {
+            let index = *self.sort_store.convert_path_to_child_path(path)?.get_indices().first()? as usize;
+            if let Some(subdir) = subdirs.get(index) {
+                let unwrapped_subdir = subdir.lock().unwrap();
+                Some(removal::Target {
+                    parent: current.clone(),
+                    entry: removal::Entry::Directory(subdir.clone()),
+                    path: unwrapped_subdir.get_path().to_path_buf(),
+                    size: unwrapped_subdir.get_size_for(self.model.size_mode)
+                })
+            }
+            else {
+                files.get(index - subdirs.len()).map(|file| removal::Target {
+                    parent: current.clone(),
+                    entry: removal::Entry::File(file.get_os_name().to_os_string()),
+                    path: current_unlocked.get_file_path(file),
+                    size: file.get_size_for(self.model.size_mode)
+                })
+            }
+        }).collect()
+    }
+
+    fn confirm_removal(&self, targets: &[removal::Target], method: removal::Method) -> bool {
+        let what = match targets {
+            [target] => format!("“{}”", target.path.file_name().unwrap_or_default().to_string_lossy()),
+            _ => format!("{} items", targets.len())
+        };
+        let total_size = targets.iter().map(|target| target.size).sum::<u64>().file_size(options::CONVENTIONAL).unwrap();
+        let (msg, detail, action) = match method {
+            removal::Method::Trash => (format!("Move {} to the trash?", what),
+                                       format!("{} in total. It can be restored from the trash.", total_size),
+                                       "_Move to Trash"),
+            removal::Method::Delete => (format!("Permanently delete {}?", what),
+                                        format!("{} in total. This can't be undone.", total_size),
+                                        "_Delete")
+        };
+
+        let dialog = gtk::MessageDialog::new(Some(&self.window), gtk::DialogFlags::MODAL, gtk::MessageType::Warning,
+                                             gtk::ButtonsType::None, &msg);
+        dialog.set_property_secondary_text(Some(&detail));
+        dialog.add_button("_Cancel", gtk::ResponseType::Cancel);
+        dialog.add_button(action, gtk::ResponseType::Accept);
+        dialog.set_default_response(gtk::ResponseType::Cancel);
+        let response = dialog.run();
+        dialog.hide();
+        response == gtk::ResponseType::Accept
+    }
+
+    /// Whether the flat file list is what's showing, which is the only place entries are removed from.
+    fn is_file_list_shown(&self) -> bool {
+        self.notebook.get_current_page() == Some(0) && self.list_stack.get_visible_child_name().as_deref() == Some("list")
+    }
+
+    fn update_removal_buttons(&self) {
+        let shown = self.is_file_list_shown();
+        self.trash_button.set_sensitive(shown);
+        self.delete_button.set_sensitive(shown);
+    }
+
+    fn on_remove(&self, method: removal::Method) {
+        if !self.is_file_list_shown() {
+            return;
+        }
+        let targets = self.get_selected_targets();
+        self.remove(targets, method);
+    }
+
+    /// Removes `targets` from disk in the background once the user has confirmed it.
+    fn remove(&self, targets: Vec<removal::Target>, method: removal::Method) {
+        if targets.is_empty() || !self.confirm_removal(&targets, method) {
+            return;
+        }
+
+        let stream = self.model.relm.stream().clone();
+        let (_, sender) = Channel::new(move |outcomes| {
+            stream.emit(AnalyzerMsg::Removed(outcomes));
+        });
+
+        thread::spawn(move || {
+            sender.send(removal::remove(targets, method)).expect("Couldn't send message");
+        });
+    }
+
+    /// Takes whatever was removed out of the tree and reports anything that couldn't be.
+    fn on_removed(&mut self, outcomes: Vec<removal::Outcome>) {
+        let mut failures = Vec::new();
+        let mut removed_paths = Vec::new();
+        for (target, result) in outcomes.iter() {
+            match result {
+                Ok(()) => {
+                    removal::apply(&self.model.root, target);
+                    removed_paths.push(target.path.as_path());
+                },
+                Err(e) => failures.push(format!("{}: {}", target.path.display(), e))
+            }
+        }
+        self.duplicate_list.remove_files(&removed_paths);
+        self.update_duplicates_label();
+
+        // The current directory may have been removed in the meantime through another route, such as the tree view.
+        let current = navigation::Location::of(&self.model.current.upgrade().expect("Current dir shouldn't be none"));
+        let dir = current.resolve(&self.model.root);
+        self.show_directory(&dir);
+        self.update_top_entries();
+
+        if !failures.is_empty() {
+            let msg = format!("Some entries could not be removed\n\n{}", failures.join("\n"));
+            let message_box = gtk::MessageDialog::new(Some(&self.window), gtk::DialogFlags::MODAL, gtk::MessageType::Error,
+                                                      gtk::ButtonsType::Ok, &msg);
+            message_box.run();
+            message_box.hide();
         }
     }
 }
@@ -164,14 +861,22 @@ impl AnalyzerWindow {
 
 impl Update for AnalyzerWindow {
     type Model = AnalyzerModel;
-    type ModelParam = Arc<Mutex<dir_walker::Directory>>;
+    type ModelParam = (Arc<Mutex<dir_walker::Directory>>, dir_walker::ScanInfo);
     type Msg = AnalyzerMsg;
 
-    fn model(_: &Relm<Self>, dir: Self::ModelParam) -> AnalyzerModel {
+    fn model(relm: &Relm<Self>, (dir, scan_info): Self::ModelParam) -> AnalyzerModel {
         let current_ref = Arc::downgrade(&dir);
         AnalyzerModel {
             root: dir,
-            current: current_ref
+            current: current_ref,
+            size_mode: dir_walker::SizeMode::Apparent,
+            scan_info,
+            baseline: None,
+            history: navigation::History::default(),
+            watcher: None,
+            finding_top_entries: false,
+            top_entries_outdated: false,
+            relm: relm.clone()
         }
     }
 
@@ -179,7 +884,54 @@ impl Update for AnalyzerWindow {
         match event {
             AnalyzerMsg::Quit => gtk::main_quit(),
             AnalyzerMsg::RowActivated(path) => self.on_row_activated(path),
-            AnalyzerMsg::Up => self.on_up_clicked()
+            AnalyzerMsg::Up => self.on_up_clicked(),
+            AnalyzerMsg::Back => self.on_history(false),
+            AnalyzerMsg::Forward => self.on_history(true),
+            AnalyzerMsg::NavigateTo(location) => {
+                let dir = location.resolve(&self.model.root);
+                self.navigate_to(&dir);
+            },
+            AnalyzerMsg::ShowAllocatedSize(show_allocated) => self.on_show_allocated_size(show_allocated),
+            AnalyzerMsg::Save => self.on_save(),
+            AnalyzerMsg::Compare => self.on_compare(),
+            AnalyzerMsg::TreeRowActivated(path) => {
+                if let Some(dir) = self.hierarchy.get_directory(&path) {
+                    self.open_directory(&dir);
+                }
+            },
+            AnalyzerMsg::ShowTree(show_tree) => {
+                self.list_stack.set_visible_child_name(if show_tree { "tree" } else { "list" });
+                self.update_removal_buttons();
+            },
+            AnalyzerMsg::PageChanged => self.update_removal_buttons(),
+            AnalyzerMsg::TreemapClicked((x, y)) => {
+                if let Some(dir) = self.treemap.get_directory_at(x, y) {
+                    self.open_directory(&dir);
+                }
+            },
+            AnalyzerMsg::RingChartClicked((x, y)) => {
+                if let Some(dir) = self.ring_chart.get_directory_at(x, y) {
+                    self.open_directory(&dir);
+                }
+            },
+            AnalyzerMsg::GotBaseline(result) => self.on_baseline_loaded(result),
+            AnalyzerMsg::Remove(method) => self.on_remove(method),
+            AnalyzerMsg::Removed(outcomes) => self.on_removed(outcomes),
+            AnalyzerMsg::Rescan => self.on_rescan(),
+            AnalyzerMsg::Rescanned((old_dir, new_dir)) => self.on_rescanned(old_dir, new_dir),
+            AnalyzerMsg::Watch(watch) => self.on_watch(watch),
+            AnalyzerMsg::GotWatchEvents(events) => self.on_watch_events(events),
+            AnalyzerMsg::FindDuplicates => self.on_find_duplicates(),
+            AnalyzerMsg::GotDuplicates(sets) => self.on_duplicates_found(sets),
+            AnalyzerMsg::DuplicateRowActivated(path) => self.on_open_duplicate(self.duplicate_list.get_file(&path)),
+            AnalyzerMsg::OpenDuplicate => self.on_open_duplicate(self.duplicate_list.get_selected_file()),
+            AnalyzerMsg::RemoveDuplicate(method) => self.on_remove_duplicate(method),
+            AnalyzerMsg::GotTopEntries(entries) => self.on_top_entries_found(entries),
+            AnalyzerMsg::TopFileActivated(path) => self.show_entry(self.top_view.get_file_path(&path)),
+            AnalyzerMsg::TopDirectoryActivated(path) => self.show_entry(self.top_view.get_directory_path(&path)),
+            AnalyzerMsg::ShowContextMenu(event) => self.on_show_context_menu(event),
+            event @ AnalyzerMsg::Open | event @ AnalyzerMsg::OpenContainingFolder | event @ AnalyzerMsg::CopyPath
+                | event @ AnalyzerMsg::OpenTerminal => self.on_context_action(event)
         }
     }
 }
@@ -191,15 +943,40 @@ impl Widget for AnalyzerWindow {
         self.window.clone()
     }
 
+    fn init_view(&mut self) {
+        let root = self.model.root.clone();
+        self.update_navigation(&root);
+        self.update_errors();
+        self.update_top_entries();
+    }
+
     fn view(relm: &Relm<Self>, model: Self::Model) -> Self {
         let file_list = gtk::TreeView::new();
-        create_analyzer_columns(&file_list);
+        let change_column = create_analyzer_columns(&file_list);
+        file_list.get_selection().set_mode(gtk::SelectionMode::Multiple);
 
-        let file_model = gtk::ListStore::new(&[String::static_type(), String::static_type(), u64::static_type(), u64::static_type()]);
+        let file_model = gtk::ListStore::new(&[String::static_type(), String::static_type(), u64::static_type(), u64::static_type(),
+                                               i64::static_type(), String::static_type()]);
         let sortable_store = gtk::TreeModelSort::new(&file_model);
         sortable_store.set_sort_column_id(gtk::SortColumn::Index(3), gtk::SortType::Descending);
         file_list.set_model(Some(&sortable_store));
-        fill_list_store(&file_model, &model.root);
+        fill_list_store(&file_model, &model.root, None, model.size_mode);
+
+        let context_menu = gtk::Menu::new();
+        let open_item = gtk::MenuItem::with_label("Open");
+        let open_folder_item = gtk::MenuItem::with_label("Open containing folder");
+        let copy_path_item = gtk::MenuItem::with_label("Copy path");
+        let terminal_item = gtk::MenuItem::with_label("Open terminal here");
+        connect!(relm, open_item, connect_activate(_), AnalyzerMsg::Open);
+        connect!(relm, open_folder_item, connect_activate(_), AnalyzerMsg::OpenContainingFolder);
+        connect!(relm, copy_path_item, connect_activate(_), AnalyzerMsg::CopyPath);
+        connect!(relm, terminal_item, connect_activate(_), AnalyzerMsg::OpenTerminal);
+        context_menu.append(&open_item);
+        context_menu.append(&open_folder_item);
+        context_menu.append(&copy_path_item);
+        context_menu.append(&terminal_item);
+        context_menu.show_all();
+        context_menu.set_property_attach_widget(Some(&file_list));
 
         let viewport = gtk::Viewport::new::<gtk::Adjustment, gtk::Adjustment>(None, None);
         viewport.add(&file_list);
@@ -208,16 +985,127 @@ impl Widget for AnalyzerWindow {
         scrolled.add(&viewport);
         scrolled.set_vexpand(true);
 
+        let hierarchy = hierarchy_view::HierarchyView::new();
+        let root_size = model.root.lock().unwrap().get_size_for(model.size_mode);
+        hierarchy.set_directory(&model.root, root_size, model.size_mode);
+        let tree_scrolled = gtk::ScrolledWindow::new::<gtk::Adjustment, gtk::Adjustment>(None, None);
+        tree_scrolled.add(hierarchy.get_widget());
+
+        let list_stack = gtk::Stack::new();
+        list_stack.add_named(&scrolled, "list");
+        list_stack.add_named(&tree_scrolled, "tree");
+
+        let treemap = treemap::Treemap::new();
+        treemap.set_directory(&model.root, model.size_mode);
+        let ring_chart = ring_chart::RingChart::new();
+        ring_chart.set_directory(&model.root, model.size_mode);
+        let chart_stack = gtk::Stack::new();
+        chart_stack.add_titled(treemap.get_widget(), "treemap", "Treemap");
+        chart_stack.add_titled(ring_chart.get_widget(), "rings", "Rings");
+        let chart_switcher = gtk::StackSwitcher::new();
+        chart_switcher.set_stack(Some(&chart_stack));
+        chart_switcher.set_halign(gtk::Align::Center);
+        let chart_box = gtk::Box::new(gtk::Orientation::Vertical, 4);
+        chart_box.add(&chart_switcher);
+        chart_box.pack_start(&chart_stack, true, true, 0);
+
+        let paned = gtk::Paned::new(gtk::Orientation::Horizontal);
+        paned.pack1(&list_stack, true, false);
+        paned.pack2(&chart_box, true, false);
+        paned.set_position(400);
+
+        let notebook = gtk::Notebook::new();
+        notebook.append_page(&paned, Some(&gtk::Label::new(Some("Files"))));
+        let type_view = type_view::TypeView::new();
+        type_view.set_directory(&model.root, model.size_mode);
+        notebook.append_page(type_view.get_widget(), Some(&gtk::Label::new(Some("Types"))));
+        let top_view = top_view::TopView::new();
+        notebook.append_page(top_view.get_widget(), Some(&gtk::Label::new(Some("Largest"))));
+        let error_list = error_list::ErrorList::new();
+        let errors_label = gtk::Label::new(None);
+        notebook.append_page(error_list.get_widget(), Some(&errors_label));
+
+        // Finding duplicates reads files, so it only happens when asked for.
+        let find_duplicates_button = gtk::Button::with_label("Find duplicates");
+        let open_duplicate_button = gtk::Button::with_label("Open");
+        let trash_duplicate_button = gtk::Button::with_label("Move to Trash");
+        let delete_duplicate_button = gtk::Button::with_label("Delete");
+        let duplicates_label = gtk::Label::new(None);
+        let duplicates_toolbar = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+        duplicates_toolbar.add(&find_duplicates_button);
+        duplicates_toolbar.add(&open_duplicate_button);
+        duplicates_toolbar.add(&trash_duplicate_button);
+        duplicates_toolbar.add(&delete_duplicate_button);
+        duplicates_toolbar.pack_end(&duplicates_label, false, false, 0);
+        let duplicate_list = duplicate_list::DuplicateList::new();
+        let duplicates_scrolled = gtk::ScrolledWindow::new::<gtk::Adjustment, gtk::Adjustment>(None, None);
+        duplicates_scrolled.add(duplicate_list.get_widget());
+        duplicates_scrolled.set_vexpand(true);
+        let duplicates_page = gtk::Box::new(gtk::Orientation::Vertical, 4);
+        duplicates_page.add(&duplicates_toolbar);
+        duplicates_page.add(&duplicates_scrolled);
+        notebook.append_page(&duplicates_page, Some(&gtk::Label::new(Some("Duplicates"))));
+
+        let breadcrumbs = gtk::Box::new(gtk::Orientation::Horizontal, 2);
+        let breadcrumb_scrolled = gtk::ScrolledWindow::new::<gtk::Adjustment, gtk::Adjustment>(None, None);
+        breadcrumb_scrolled.set_policy(gtk::PolicyType::Automatic, gtk::PolicyType::Never);
+        breadcrumb_scrolled.add(&breadcrumbs);
+
+        // Problems while watching for changes, hidden until there are some.
+        let watch_warning = gtk::InfoBar::new();
+        watch_warning.set_message_type(gtk::MessageType::Warning);
+        watch_warning.set_show_close_button(true);
+        let watch_warning_label = gtk::Label::new(None);
+        watch_warning_label.set_line_wrap(true);
+        watch_warning_label.show();
+        watch_warning.get_content_area().add(&watch_warning_label);
+        watch_warning.set_no_show_all(true);
+        watch_warning.connect_response(|bar, _| bar.hide());
+
         let vbox = gtk::Box::new(gtk::Orientation::Vertical, 0);
-        vbox.add(&scrolled);
+        vbox.add(&watch_warning);
+        vbox.add(&breadcrumb_scrolled);
+        vbox.add(&notebook);
 
         let header_bar = gtk::HeaderBar::new();
         let up_button = gtk::Button::from_icon_name(Some("go-up"), gtk::IconSize::Menu);
         up_button.set_tooltip_text(Some("Up"));
+        let back_button = gtk::Button::from_icon_name(Some("go-previous"), gtk::IconSize::Menu);
+        back_button.set_tooltip_text(Some("Back"));
+        let forward_button = gtk::Button::from_icon_name(Some("go-next"), gtk::IconSize::Menu);
+        forward_button.set_tooltip_text(Some("Forward"));
+        let allocated_button = gtk::ToggleButton::with_label("On disk");
+        allocated_button.set_tooltip_text(Some("Show space allocated on disk instead of apparent file sizes"));
+        let tree_button = gtk::ToggleButton::with_label("Tree");
+        tree_button.set_tooltip_text(Some("Expand directories in place instead of listing one at a time"));
+        let save_button = gtk::Button::from_icon_name(Some("document-save-as"), gtk::IconSize::Menu);
+        save_button.set_tooltip_text(Some("Save scan"));
+        let trash_button = gtk::Button::from_icon_name(Some("user-trash"), gtk::IconSize::Menu);
+        trash_button.set_tooltip_text(Some("Move the selected entries to the trash"));
+        let delete_button = gtk::Button::from_icon_name(Some("edit-delete"), gtk::IconSize::Menu);
+        delete_button.set_tooltip_text(Some("Permanently delete the selected entries"));
+        let rescan_button = gtk::Button::from_icon_name(Some("view-refresh"), gtk::IconSize::Menu);
+        rescan_button.set_tooltip_text(Some("Rescan this folder"));
+        let rescan_spinner = gtk::Spinner::new();
+        let watch_button = gtk::ToggleButton::with_label("Watch");
+        watch_button.set_tooltip_text(Some("Keep the scan up to date as files change on disk"));
+        let compare_button = gtk::Button::with_label("Compare");
+        compare_button.set_tooltip_text(Some("Compare with an earlier saved scan of the same location"));
         header_bar.set_title(Some("Disk Analyzer"));
-        header_bar.set_subtitle(Some(model.root.lock().unwrap().get_path()));
+        header_bar.set_subtitle(Some(&model.root.lock().unwrap().get_display_path()));
         header_bar.set_show_close_button(true);
+        header_bar.pack_start(&back_button);
+        header_bar.pack_start(&forward_button);
         header_bar.pack_start(&up_button);
+        header_bar.pack_start(&rescan_button);
+        header_bar.pack_start(&rescan_spinner);
+        header_bar.pack_start(&trash_button);
+        header_bar.pack_start(&delete_button);
+        header_bar.pack_end(&save_button);
+        header_bar.pack_end(&compare_button);
+        header_bar.pack_end(&allocated_button);
+        header_bar.pack_end(&tree_button);
+        header_bar.pack_end(&watch_button);
         
         let window = gtk::Window::new(WindowType::Toplevel);
         window.add(&vbox);
@@ -227,14 +1115,71 @@ impl Widget for AnalyzerWindow {
 
         connect!(relm, window, connect_delete_event(_, _), return (Some(AnalyzerMsg::Quit), Inhibit(false)));
         connect!(relm, up_button, connect_clicked(_), AnalyzerMsg::Up);
+        connect!(relm, back_button, connect_clicked(_), AnalyzerMsg::Back);
+        connect!(relm, forward_button, connect_clicked(_), AnalyzerMsg::Forward);
+        connect!(relm, window, connect_key_press_event(window, event),
+                 return (get_shortcut(event, matches!(window.get_focus(), Some(widget) if widget.is::<gtk::Editable>())), Inhibit(false)));
+        connect!(relm, file_list, connect_key_press_event(_, event), return (get_removal_shortcut(event), Inhibit(false)));
+        connect!(relm, notebook, connect_property_page_notify(_), AnalyzerMsg::PageChanged);
+        connect!(relm, allocated_button, connect_toggled(btn), AnalyzerMsg::ShowAllocatedSize(btn.get_active()));
+        connect!(relm, tree_button, connect_toggled(btn), AnalyzerMsg::ShowTree(btn.get_active()));
+        connect!(relm, hierarchy.get_widget(), connect_row_activated(_, path, _), AnalyzerMsg::TreeRowActivated(path.clone()));
+        connect!(relm, save_button, connect_clicked(_), AnalyzerMsg::Save);
+        connect!(relm, compare_button, connect_clicked(_), AnalyzerMsg::Compare);
+        connect!(relm, rescan_button, connect_clicked(_), AnalyzerMsg::Rescan);
+        connect!(relm, watch_button, connect_toggled(btn), AnalyzerMsg::Watch(btn.get_active()));
+        connect!(relm, find_duplicates_button, connect_clicked(_), AnalyzerMsg::FindDuplicates);
+        connect!(relm, open_duplicate_button, connect_clicked(_), AnalyzerMsg::OpenDuplicate);
+        connect!(relm, trash_duplicate_button, connect_clicked(_), AnalyzerMsg::RemoveDuplicate(removal::Method::Trash));
+        connect!(relm, delete_duplicate_button, connect_clicked(_), AnalyzerMsg::RemoveDuplicate(removal::Method::Delete));
+        connect!(relm, duplicate_list.get_widget(), connect_row_activated(_, path, _),
+                 AnalyzerMsg::DuplicateRowActivated(path.clone()));
+        connect!(relm, top_view.get_file_list(), connect_row_activated(_, path, _), AnalyzerMsg::TopFileActivated(path.clone()));
+        connect!(relm, top_view.get_directory_list(), connect_row_activated(_, path, _),
+                 AnalyzerMsg::TopDirectoryActivated(path.clone()));
+        connect!(relm, trash_button, connect_clicked(_), AnalyzerMsg::Remove(removal::Method::Trash));
+        connect!(relm, delete_button, connect_clicked(_), AnalyzerMsg::Remove(removal::Method::Delete));
         connect!(relm, file_list, connect_row_activated(_, path, _), AnalyzerMsg::RowActivated(path.clone()));
+        connect!(relm, file_list, connect_button_press_event(_, event),
+                 return (if event.get_button() == 3 { Some(AnalyzerMsg::ShowContextMenu(event.clone())) } else { None },
+                         Inhibit(event.get_button() == 3)));
+        connect!(relm, treemap.get_widget(), connect_button_press_event(_, event),
+                 return (if event.get_button() == 1 { Some(AnalyzerMsg::TreemapClicked(event.get_position())) } else { None }, Inhibit(false)));
+        connect!(relm, ring_chart.get_widget(), connect_button_press_event(_, event),
+                 return (if event.get_button() == 1 { Some(AnalyzerMsg::RingChartClicked(event.get_position())) } else { None }, Inhibit(false)));
 
         AnalyzerWindow {
             model,
             window,
             list_store: file_model,
             sort_store: sortable_store,
-            header_bar: header_bar
+            file_list,
+            context_menu,
+            header_bar: header_bar,
+            change_column,
+            treemap,
+            ring_chart,
+            hierarchy,
+            list_stack,
+            breadcrumbs,
+            back_button,
+            forward_button,
+            up_button,
+            rescan_button,
+            rescan_spinner,
+            watch_button,
+            duplicate_list,
+            duplicates_label,
+            find_duplicates_button,
+            type_view,
+            top_view,
+            notebook,
+            trash_button,
+            delete_button,
+            error_list,
+            errors_label,
+            watch_warning,
+            watch_warning_label
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,198 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use humansize::{FileSize, file_size_opts as options};
+use std::cmp::Reverse;
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::sync::mpsc::channel;
+use super::dir_walker;
+use super::snapshot;
+
+static USAGE: &str = "Usage: disk_analyzer scan <path> [options]
+
+Prints the largest directories and files below <path> instead of opening the
+analyzer window. To open the window on a directory that is itself called scan,
+run disk_analyzer ./scan or disk_analyzer -- scan.
+
+Options:
+    --top <n>             Number of directories and files to list (default 20)
+    --depth <n>           Only list entries at most <n> levels below <path>
+    --threads <n>         Number of scanning threads (default: one per CPU)
+    --allocated           Report space allocated on disk instead of apparent size
+    --one-file-system     Don't descend into other mounted file systems
+    --symlinks <policy>   ignore, list or follow symbolic links (default list)
+    --mime <method>       Detect file types by extension, by the contents of files
+                          with an unknown extension (unknown), or by the contents of
+                          every file (content) (default extension)
+    --save <file>         Save the scan to be opened later in the analyzer window
+                          (as JSON if <file> ends in .json, compact binary otherwise)
+    --help                Show this message";
+
+struct CliOptions {
+    path: PathBuf,
+    top: usize,
+    depth: Option<usize>,
+    size_mode: dir_walker::SizeMode,
+    scan_options: dir_walker::ScanOptions,
+    save_path: Option<PathBuf>
+}
+
+fn parse_number(name: &str, value: Option<&OsString>) -> Result<usize, String> {
+    value.and_then(|v| v.to_str())
+         .and_then(|v| v.parse::<usize>().ok())
+         .ok_or_else(|| format!("{} expects a number", name))
+}
+
+fn parse_args(args: &[OsString]) -> Result<CliOptions, String> {
+    let mut path = None;
+    let mut top = 20;
+    let mut depth = None;
+    let mut size_mode = dir_walker::SizeMode::Apparent;
+    let mut scan_options = dir_walker::ScanOptions::default();
+    let mut save_path = None;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.to_str() {
+            Some("--top") => top = parse_number("--top", iter.next())?,
+            Some("--depth") => depth = Some(parse_number("--depth", iter.next())?),
+            Some("--threads") => scan_options.threads = parse_number("--threads", iter.next())?,
+            Some("--allocated") => size_mode = dir_walker::SizeMode::Allocated,
+            Some("--one-file-system") => scan_options.one_filesystem = true,
+            Some("--save") => save_path = Some(PathBuf::from(iter.next().ok_or("--save expects a file name")?)),
+            Some("--symlinks") => {
+                scan_options.symlinks = match iter.next().and_then(|v| v.to_str()) {
+                    Some("ignore") => dir_walker::SymlinkPolicy::Ignore,
+                    Some("list") => dir_walker::SymlinkPolicy::List,
+                    Some("follow") => dir_walker::SymlinkPolicy::Follow,
+                    _ => return Err("--symlinks expects ignore, list or follow".to_string())
+                }
+            },
+            Some("--mime") => {
+                scan_options.mime_detection = match iter.next().and_then(|v| v.to_str()) {
+                    Some("extension") => dir_walker::MimeDetection::Extension,
+                    Some("unknown") => dir_walker::MimeDetection::SniffUnknown,
+                    Some("content") => dir_walker::MimeDetection::SniffAll,
+                    _ => return Err("--mime expects extension, unknown or content".to_string())
+                }
+            },
+            Some(option) if option.starts_with("--") => return Err(format!("Unknown option {}", option)),
+            _ if path.is_none() => path = Some(PathBuf::from(arg)),
+            _ => return Err("Only one path can be scanned".to_string())
+        }
+    }
+
+    match path {
+        Some(path) => Ok(CliOptions { path, top, depth, size_mode, scan_options, save_path }),
+        None => Err("No path given".to_string())
+    }
+}
+
+/// Gathers (size, path) pairs for every directory and file no deeper than `max_depth`.
+fn collect_entries(dir: &Mutex<dir_walker::Directory>, depth: usize, max_depth: Option<usize>,
+                   size_mode: dir_walker::SizeMode, directories: &mut Vec<(u64, PathBuf)>,
+                   files: &mut Vec<(u64, PathBuf)>) {
+    if matches!(max_depth, Some(max) if depth > max) {
+        return;
+    }
+
+    let unwrapped_dir = dir.lock().unwrap();
+    if depth > 0 {
+        directories.push((unwrapped_dir.get_size_for(size_mode), unwrapped_dir.get_path().to_path_buf()));
+    }
+    for file in unwrapped_dir.get_files() {
+        if !matches!(max_depth, Some(max) if depth >= max) {
+            files.push((file.get_size_for(size_mode), unwrapped_dir.get_file_path(file)));
+        }
+    }
+    for subdir in unwrapped_dir.get_subdirectories() {
+        collect_entries(subdir, depth + 1, max_depth, size_mode, directories, files);
+    }
+}
+
+fn print_table(title: &str, entries: &mut Vec<(u64, PathBuf)>, total_size: u64, top: usize) {
+    entries.sort_by_key(|entry| Reverse(entry.0));
+    entries.truncate(top);
+
+    println!("{}", title);
+    println!("{:>12} {:>6}  Path", "Size", "%");
+    for (size, path) in entries.iter() {
+        let percentage = if total_size > 0 { (*size as f64 / total_size as f64) * 100.0 } else { 0.0 };
+        println!("{:>12} {:>5.1}%  {}", size.file_size(options::CONVENTIONAL).unwrap(), percentage, path.display());
+    }
+    println!();
+}
+
+#[cfg(windows)]
+extern "system" {
+    fn AttachConsole(process_id: u32) -> i32;
+}
+
+/// The program is built for the windows subsystem, so it has no console of its own to print to.
+/// Borrowing the one it was started from makes the output show up there.
+#[cfg(windows)]
+fn attach_console() {
+    const ATTACH_PARENT_PROCESS: u32 = u32::MAX;
+    // Failing just means there's nowhere to print, as when started from Explorer.
+    unsafe { AttachConsole(ATTACH_PARENT_PROCESS) };
+}
+
+#[cfg(not(windows))]
+fn attach_console() {}
+
+/// Runs `disk_analyzer scan`. `args` are the arguments following "scan".
+/// Returns the process exit code.
+pub fn run(args: &[OsString]) -> i32 {
+    attach_console();
+    if args.iter().any(|arg| arg == "--help") {
+        println!("{}", USAGE);
+        return 0;
+    }
+
+    let cli_options = match parse_args(args) {
+        Ok(cli_options) => cli_options,
+        Err(message) => {
+            eprintln!("{}\n\n{}", message, USAGE);
+            return 2;
+        }
+    };
+
+    // Nothing ever cancels a command line scan, but the walker still needs a channel to watch.
+    let (_cancel_sender, cancel_receiver) = channel();
+    let progress = dir_walker::ScanProgress::new();
+    let scan_info = dir_walker::ScanInfo::new(std::slice::from_ref(&cli_options.path), &cli_options.scan_options);
+    let root = dir_walker::read_dir(&cli_options.path, cancel_receiver, &cli_options.scan_options, &progress);
+
+    if let Some(e) = root.lock().unwrap().get_error() {
+        eprintln!("Could not scan {}: {}", cli_options.path.display(), e);
+        return 1;
+    }
+
+    let total_size = root.lock().unwrap().get_size_for(cli_options.size_mode);
+    let mut directories = Vec::new();
+    let mut files = Vec::new();
+    collect_entries(&root, 0, cli_options.depth, cli_options.size_mode, &mut directories, &mut files);
+
+    println!("{}: {}\n", cli_options.path.display(), total_size.file_size(options::CONVENTIONAL).unwrap());
+    print_table("Largest directories", &mut directories, total_size, cli_options.top);
+    print_table("Largest files", &mut files, total_size, cli_options.top);
+
+    let errors = dir_walker::collect_errors(&root);
+    if !errors.is_empty() {
+        eprintln!("{} entries could not be read:", errors.len());
+        for error in errors {
+            eprintln!("    {}", error);
+        }
+    }
+
+    if let Some(save_path) = cli_options.save_path {
+        if let Err(e) = snapshot::save(&save_path, &root, &scan_info) {
+            eprintln!("Could not save the scan to {}: {}", save_path.display(), e);
+            return 1;
+        }
+    }
+    0
+}
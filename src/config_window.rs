@@ -4,16 +4,19 @@
 
 use gtk::{Window, Inhibit, WindowType};
 use gtk::prelude::*;
-use relm::{connect, Channel, Relm, Update, Widget, Component, init};
+use humansize::{FileSize, file_size_opts as options};
+use relm::{connect, interval, Channel, Relm, Update, Widget, Component, init};
 use relm_derive::Msg;
 use std::thread;
 use std::sync::{Arc, Mutex};
 use std::sync::mpsc::{channel, Sender};
 use super::dir_walker;
 use super::analyzer;
+use super::dialogs;
+use super::snapshot;
 
 pub struct ConfigModel {
-    path: Option<std::path::PathBuf>,
+    paths: Vec<std::path::PathBuf>,
     relm: Relm<ConfigWindow>
 }
 
@@ -23,17 +26,91 @@ pub enum ConfigMsg {
     GotPath(Option<std::path::PathBuf>),
     StartScan,
     GotResults(Arc<Mutex<dir_walker::Directory>>),
-    CancelScan
+    CancelScan,
+    UpdateProgress,
+    OpenSnapshot,
+    GotSnapshot(Result<(Arc<Mutex<dir_walker::Directory>>, dir_walker::ScanInfo), snapshot::SnapshotError>)
+}
+
+/// Labels showing the live counters of a running scan.
+struct ProgressArea {
+    container: gtk::Grid,
+    files: gtk::Label,
+    directories: gtk::Label,
+    bytes: gtk::Label,
+    errors: gtk::Label,
+    current_path: gtk::Label
+}
+
+impl ProgressArea {
+    fn new() -> ProgressArea {
+        let container = gtk::Grid::new();
+        container.set_column_spacing(10);
+        container.set_row_spacing(2);
+        // Stays hidden until a scan starts.
+        container.set_no_show_all(true);
+
+        let add_row = |row: i32, title: &str| {
+            let title_label = gtk::Label::new(Some(title));
+            title_label.set_halign(gtk::Align::Start);
+            let value_label = gtk::Label::new(None);
+            value_label.set_halign(gtk::Align::Start);
+            title_label.show();
+            value_label.show();
+            container.attach(&title_label, 0, row, 1, 1);
+            container.attach(&value_label, 1, row, 1, 1);
+            value_label
+        };
+
+        let files = add_row(0, "Files");
+        let directories = add_row(1, "Directories");
+        let bytes = add_row(2, "Size");
+        let errors = add_row(3, "Errors");
+
+        let current_path = gtk::Label::new(None);
+        current_path.set_halign(gtk::Align::Start);
+        current_path.set_ellipsize(pango::EllipsizeMode::Middle);
+        current_path.set_max_width_chars(40);
+        current_path.show();
+        container.attach(&current_path, 0, 4, 2, 1);
+
+        ProgressArea {
+            container,
+            files,
+            directories,
+            bytes,
+            errors,
+            current_path
+        }
+    }
+
+    fn update(&self, progress: &dir_walker::ProgressSnapshot) {
+        self.files.set_text(&progress.files.to_string());
+        self.directories.set_text(&progress.directories.to_string());
+        self.bytes.set_text(&progress.bytes.file_size(options::CONVENTIONAL).unwrap());
+        self.errors.set_text(&progress.errors.to_string());
+        self.current_path.set_text(&progress.current_path);
+    }
 }
 
 pub struct ConfigWindow {
     model: ConfigModel,
     window: Window,
+    /// Everything used to set up a scan, hidden while scanning paths given on the command line.
+    options_box: gtk::Box,
     file_chooser: gtk::FileChooserButton,
     scan_button: gtk::Button,
+    threads_button: gtk::SpinButton,
+    one_filesystem_button: gtk::CheckButton,
+    symlinks_combo: gtk::ComboBoxText,
+    mime_combo: gtk::ComboBoxText,
     analyzer_win: Option<Component<analyzer::AnalyzerWindow>>,
     cancel_sender: Option<Sender<()>>,
-    cancel_button: gtk::Button
+    cancel_button: gtk::Button,
+    progress: Option<Arc<dir_walker::ScanProgress>>,
+    progress_area: ProgressArea,
+    scan_info: Option<dir_walker::ScanInfo>,
+    open_button: gtk::Button
 }
 
 impl ConfigWindow {
@@ -41,11 +118,38 @@ impl ConfigWindow {
         self.scan_button.set_label("Load");
         self.scan_button.set_sensitive(true);
         self.file_chooser.set_sensitive(true);
+        self.threads_button.set_sensitive(true);
+        self.one_filesystem_button.set_sensitive(true);
+        self.symlinks_combo.set_sensitive(true);
+        self.mime_combo.set_sensitive(true);
+        self.open_button.set_sensitive(true);
         self.cancel_button.set_sensitive(false);
+        self.progress_area.container.hide();
+        // Paths from the command line skip the options, bring them back so another directory can be picked.
+        self.options_box.show();
+        self.open_button.show();
+        self.window.set_title("Choose a directory to scan");
+    }
+
+    fn get_symlink_policy(&self) -> dir_walker::SymlinkPolicy {
+        match self.symlinks_combo.get_active_id().as_ref().map(|id| id.as_str()) {
+            Some("ignore") => dir_walker::SymlinkPolicy::Ignore,
+            Some("follow") => dir_walker::SymlinkPolicy::Follow,
+            _ => dir_walker::SymlinkPolicy::List
+        }
+    }
+
+    fn get_mime_detection(&self) -> dir_walker::MimeDetection {
+        match self.mime_combo.get_active_id().as_ref().map(|id| id.as_str()) {
+            Some("sniff_unknown") => dir_walker::MimeDetection::SniffUnknown,
+            Some("sniff_all") => dir_walker::MimeDetection::SniffAll,
+            _ => dir_walker::MimeDetection::Extension
+        }
     }
 
     fn on_scan_start(&mut self) {
-        if let Some(file_path) = self.model.path.clone() {
+        if !self.model.paths.is_empty() {
+            let paths = self.model.paths.clone();
             let stream = self.model.relm.stream().clone();
             let (_, sender) = Channel::new(move |dir| {
                 stream.emit(ConfigMsg::GotResults(dir));
@@ -54,13 +158,30 @@ impl ConfigWindow {
             let (send, recv) = channel();
             self.cancel_sender = Some(send);
 
+            let options = dir_walker::ScanOptions {
+                threads: self.threads_button.get_value_as_int() as usize,
+                one_filesystem: self.one_filesystem_button.get_active(),
+                symlinks: self.get_symlink_policy(),
+                mime_detection: self.get_mime_detection()
+            };
+            self.scan_info = Some(dir_walker::ScanInfo::new(&paths, &options));
+            let progress = Arc::new(dir_walker::ScanProgress::new());
+            self.progress = Some(progress.clone());
+            self.progress_area.update(&progress.snapshot());
+            self.progress_area.container.show();
+
             self.scan_button.set_label("Reading...");
             self.scan_button.set_sensitive(false);
             self.file_chooser.set_sensitive(false);
+            self.threads_button.set_sensitive(false);
+            self.one_filesystem_button.set_sensitive(false);
+            self.symlinks_combo.set_sensitive(false);
+            self.mime_combo.set_sensitive(false);
+            self.open_button.set_sensitive(false);
             self.cancel_button.set_sensitive(true);
 
             thread::spawn(move || {
-                let dir = dir_walker::read_dir(&file_path, &recv);
+                let dir = dir_walker::read_dirs(&paths, recv, &options, &progress);
                 sender.send(dir).expect("Couldn't send message");
             });
         }
@@ -68,18 +189,17 @@ impl ConfigWindow {
 
     fn on_scan_complete(&mut self, dir: Arc<Mutex<dir_walker::Directory>>) {
         self.cancel_sender = None;
+        self.progress = None;
         let dir_clone = dir.clone();
         let error = dir.lock().unwrap().get_error().clone();
         match error {
             None => {
-                self.window.hide();
-                let analyzer_win = init::<analyzer::AnalyzerWindow>(dir_clone).expect("Couldn't init");
-                analyzer_win.widget().show_all();
-                self.analyzer_win = Some(analyzer_win);
+                let scan_info = self.scan_info.take().expect("Scan info should be set when a scan starts");
+                self.open_analyzer(dir_clone, scan_info);
             },
             Some(e) => match e {
-                dir_walker::ReadError::IOError(_) => {
-                    let msg = "Could not read directory contents";
+                dir_walker::ReadError::IOError { .. } => {
+                    let msg = format!("Could not read directory contents\n\n{}", e);
                     let message_box = gtk::MessageDialog::new(Some(&self.window), gtk::DialogFlags::MODAL, gtk::MessageType::Error,
                                                               gtk::ButtonsType::Ok, &msg);
                     message_box.run();
@@ -91,33 +211,86 @@ impl ConfigWindow {
         }
     }
 
+    fn open_analyzer(&mut self, dir: Arc<Mutex<dir_walker::Directory>>, scan_info: dir_walker::ScanInfo) {
+        self.window.hide();
+        let analyzer_win = init::<analyzer::AnalyzerWindow>((dir, scan_info)).expect("Couldn't init");
+        analyzer_win.widget().show_all();
+        self.analyzer_win = Some(analyzer_win);
+    }
+
+    fn on_open_snapshot(&mut self) {
+        if let Some(file_path) = dialogs::choose_file(&self.window, "Open saved scan", None) {
+            let stream = self.model.relm.stream().clone();
+            let (_, sender) = Channel::new(move |result| {
+                stream.emit(ConfigMsg::GotSnapshot(result));
+            });
+
+            self.scan_button.set_label("Loading...");
+            self.scan_button.set_sensitive(false);
+            self.file_chooser.set_sensitive(false);
+            self.open_button.set_sensitive(false);
+
+            thread::spawn(move || {
+                sender.send(snapshot::load(&file_path)).expect("Couldn't send message");
+            });
+        }
+    }
+
+    fn on_snapshot_loaded(&mut self, result: Result<(Arc<Mutex<dir_walker::Directory>>, dir_walker::ScanInfo), snapshot::SnapshotError>) {
+        match result {
+            Ok((dir, scan_info)) => self.open_analyzer(dir, scan_info),
+            Err(e) => {
+                let msg = format!("Could not open saved scan\n\n{}", e);
+                let message_box = gtk::MessageDialog::new(Some(&self.window), gtk::DialogFlags::MODAL, gtk::MessageType::Error,
+                                                          gtk::ButtonsType::Ok, &msg);
+                message_box.run();
+                message_box.hide();
+                self.reset_ui();
+            }
+        }
+    }
+
     fn on_scan_cancel(&self) {
         self.cancel_button.set_sensitive(false);
         if let Some(tracker) = &self.cancel_sender {
             tracker.send(()).unwrap();
         }
     }
+
+    fn on_update_progress(&self) {
+        if let Some(progress) = &self.progress {
+            self.progress_area.update(&progress.snapshot());
+        }
+    }
 }
 
 impl Update for ConfigWindow {
     type Model = ConfigModel;
-    type ModelParam = ();
+    /// Paths given on the command line. When there are any they are scanned straight away.
+    type ModelParam = Vec<std::path::PathBuf>;
     type Msg = ConfigMsg;
     
-    fn model(relm: &Relm<Self>, _: ()) -> ConfigModel {
+    fn model(relm: &Relm<Self>, paths: Vec<std::path::PathBuf>) -> ConfigModel {
         ConfigModel {
-            path: None,
+            paths,
             relm: relm.clone()
         }
     }
 
+    fn subscriptions(&mut self, relm: &Relm<Self>) {
+        interval(relm.stream(), 250, || ConfigMsg::UpdateProgress);
+    }
+
     fn update(&mut self, event: ConfigMsg) {
         match event {
             ConfigMsg::Quit => gtk::main_quit(),
-            ConfigMsg::GotPath(path) => self.model.path = path,
+            ConfigMsg::GotPath(path) => self.model.paths = path.into_iter().collect(),
             ConfigMsg::StartScan => self.on_scan_start(),
             ConfigMsg::GotResults(result) => self.on_scan_complete(result),
-            ConfigMsg::CancelScan => self.on_scan_cancel()
+            ConfigMsg::CancelScan => self.on_scan_cancel(),
+            ConfigMsg::UpdateProgress => self.on_update_progress(),
+            ConfigMsg::OpenSnapshot => self.on_open_snapshot(),
+            ConfigMsg::GotSnapshot(result) => self.on_snapshot_loaded(result)
         }
     }
 }
@@ -138,9 +311,53 @@ impl Widget for ConfigWindow {
         cancel_button.set_label("Cancel");
         cancel_button.set_sensitive(false);
 
-        vbox.add(&file_chooser);
-        vbox.add(&scan_button);
+        let threads_box = gtk::Box::new(gtk::Orientation::Horizontal, 10);
+        let threads_label = gtk::Label::new(Some("Threads"));
+        let threads_button = gtk::SpinButton::with_range(1.0, 256.0, 1.0);
+        threads_button.set_value(dir_walker::default_thread_count() as f64);
+        threads_box.pack_start(&threads_label, false, false, 0);
+        threads_box.pack_end(&threads_button, false, false, 0);
+
+        let one_filesystem_button = gtk::CheckButton::with_label("Stay on one file system");
+        one_filesystem_button.set_tooltip_text(Some("Don't descend into other mounted file systems"));
+
+        let symlinks_box = gtk::Box::new(gtk::Orientation::Horizontal, 10);
+        let symlinks_label = gtk::Label::new(Some("Symbolic links"));
+        let symlinks_combo = gtk::ComboBoxText::new();
+        symlinks_combo.append(Some("ignore"), "Ignore");
+        symlinks_combo.append(Some("list"), "List without following");
+        symlinks_combo.append(Some("follow"), "Follow");
+        symlinks_combo.set_active_id(Some("list"));
+        symlinks_box.pack_start(&symlinks_label, false, false, 0);
+        symlinks_box.pack_end(&symlinks_combo, false, false, 0);
+
+        let mime_box = gtk::Box::new(gtk::Orientation::Horizontal, 10);
+        let mime_label = gtk::Label::new(Some("File types"));
+        let mime_combo = gtk::ComboBoxText::new();
+        mime_combo.append(Some("extension"), "By extension");
+        mime_combo.append(Some("sniff_unknown"), "Read files with unknown extensions");
+        mime_combo.append(Some("sniff_all"), "Read every file");
+        mime_combo.set_active_id(Some("extension"));
+        mime_combo.set_tooltip_text(Some("Reading files recognises more types, but makes the scan slower"));
+        mime_box.pack_start(&mime_label, false, false, 0);
+        mime_box.pack_end(&mime_combo, false, false, 0);
+
+        let options_box = gtk::Box::new(gtk::Orientation::Vertical, 10);
+        options_box.add(&file_chooser);
+        options_box.add(&threads_box);
+        options_box.add(&one_filesystem_button);
+        options_box.add(&symlinks_box);
+        options_box.add(&mime_box);
+        options_box.add(&scan_button);
+        vbox.add(&options_box);
         vbox.add(&cancel_button);
+
+        let open_button = gtk::Button::new();
+        open_button.set_label("Open saved scan...");
+        vbox.add(&open_button);
+
+        let progress_area = ProgressArea::new();
+        vbox.add(&progress_area.container);
         vbox.set_spacing(10);
 
         let window = gtk::Window::new(WindowType::Toplevel);
@@ -150,19 +367,40 @@ impl Widget for ConfigWindow {
         window.resize(300, 75);
         window.show_all();
 
+        if !model.paths.is_empty() {
+            // There's nothing to choose, go straight to scanning what we were given.
+            if let [path] = model.paths.as_slice() {
+                file_chooser.set_filename(path);
+            }
+            options_box.hide();
+            open_button.hide();
+            window.set_title("Scanning");
+            relm.stream().emit(ConfigMsg::StartScan);
+        }
+
         connect!(relm, scan_button, connect_clicked(_), ConfigMsg::StartScan);
         connect!(relm, cancel_button, connect_clicked(_), ConfigMsg::CancelScan);
+        connect!(relm, open_button, connect_clicked(_), ConfigMsg::OpenSnapshot);
         connect!(relm, file_chooser, connect_file_set(btn), ConfigMsg::GotPath(btn.get_filename()));
         connect!(relm, window, connect_delete_event(_, _), return (Some(ConfigMsg::Quit), Inhibit(false)));
 
         ConfigWindow {
             model,
             window,
+            options_box,
             file_chooser,
             scan_button,
+            threads_button,
+            one_filesystem_button,
+            symlinks_combo,
+            mime_combo,
             analyzer_win: None,
             cancel_sender: None,
-            cancel_button
+            cancel_button,
+            progress: None,
+            progress_area,
+            scan_info: None,
+            open_button
         }
     }
 }
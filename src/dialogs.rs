@@ -0,0 +1,32 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use gtk::prelude::*;
+use std::path::PathBuf;
+
+/// Asks for a file to open, or with `save_name` set, a file to save to. Returns None if the user cancelled.
+pub fn choose_file(parent: &gtk::Window, title: &str, save_name: Option<&str>) -> Option<PathBuf> {
+    let (action, accept_label) = match save_name {
+        Some(_) => (gtk::FileChooserAction::Save, "_Save"),
+        None => (gtk::FileChooserAction::Open, "_Open")
+    };
+    let dialog = gtk::FileChooserDialog::with_buttons(Some(title), Some(parent), action,
+                                                      &[("_Cancel", gtk::ResponseType::Cancel),
+                                                        (accept_label, gtk::ResponseType::Accept)]);
+    if let Some(name) = save_name {
+        dialog.set_current_name(name);
+        dialog.set_do_overwrite_confirmation(true);
+    }
+
+    let response = dialog.run();
+    let file_name = dialog.get_filename();
+    dialog.close();
+
+    if response == gtk::ResponseType::Accept {
+        file_name
+    }
+    else {
+        None
+    }
+}
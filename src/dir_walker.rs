@@ -3,38 +3,269 @@
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
 use mime_guess;
+use rayon::prelude::*;
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::ffi::{OsStr, OsString};
 use std::fmt;
 use std::fs;
-use std::path::PathBuf;
+use std::io;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Weak, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::mpsc::Receiver;
+use std::thread;
+use std::time::SystemTime;
 use thiserror::Error;
+use super::mime_sniff::{self, Sniffed};
+
+/// The filesystem call that failed while reading an entry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operation {
+    OpenDirectory,
+    ListDirectory,
+    Metadata,
+    ReadLink
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = match self {
+            Operation::OpenDirectory => "Opening directory",
+            Operation::ListDirectory => "Listing directory",
+            Operation::Metadata => "Reading metadata",
+            Operation::ReadLink => "Reading link target"
+        };
+        write!(f, "{}", description)
+    }
+}
+
+/// Broad groups of errors, used to sort failures for display.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ErrorCategory {
+    PermissionDenied,
+    /// The entry disappeared between being listed and being read.
+    NotFound,
+    Other
+}
+
+impl fmt::Display for ErrorCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = match self {
+            ErrorCategory::PermissionDenied => "Permission denied",
+            ErrorCategory::NotFound => "Not found",
+            ErrorCategory::Other => "I/O error"
+        };
+        write!(f, "{}", description)
+    }
+}
 
 #[derive(Error, Debug, Clone)]
 pub enum ReadError {
-    #[error("I/O error")]
-    IOError(std::io::ErrorKind),
+    #[error("{operation} failed for {}: {message}", .path.display())]
+    IOError {
+        path: PathBuf,
+        operation: Operation,
+        kind: std::io::ErrorKind,
+        message: String
+    },
     #[error("Operation cancelled")]
     OperationCancelled,
 }
 
+impl ReadError {
+    fn new_io(path: &Path, operation: Operation, error: std::io::Error) -> ReadError {
+        ReadError::IOError {
+            path: path.to_path_buf(),
+            operation,
+            kind: error.kind(),
+            message: error.to_string()
+        }
+    }
+
+    pub fn get_category(&self) -> ErrorCategory {
+        match self {
+            ReadError::IOError { kind: std::io::ErrorKind::PermissionDenied, .. } => ErrorCategory::PermissionDenied,
+            ReadError::IOError { kind: std::io::ErrorKind::NotFound, .. } => ErrorCategory::NotFound,
+            _ => ErrorCategory::Other
+        }
+    }
+}
+
+/// Settings that control how `read_dir` walks a directory tree.
+#[derive(Clone, Debug)]
+pub struct ScanOptions {
+    /// Number of worker threads used to scan sibling directories concurrently.
+    pub threads: usize,
+    /// Don't descend into directories on a different device than the scan root, like `du -x`.
+    pub one_filesystem: bool,
+    pub symlinks: SymlinkPolicy,
+    pub mime_detection: MimeDetection
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        ScanOptions {
+            threads: default_thread_count(),
+            one_filesystem: false,
+            symlinks: SymlinkPolicy::List,
+            mime_detection: MimeDetection::Extension
+        }
+    }
+}
+
+/// What the walker does when it comes across a symbolic link.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SymlinkPolicy {
+    /// Leave links out of the tree entirely.
+    Ignore,
+    /// Record links as zero-size entries pointing at their target.
+    List,
+    /// Scan whatever the link points at. Directories already visited are listed instead.
+    Follow
+}
+
+/// How the walker decides on the mime type of each file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MimeDetection {
+    /// Go by the file name alone, which costs nothing but calls anything unrecognised text.
+    Extension,
+    /// Read the start of files whose extension isn't recognised, which is usually only a few of them.
+    SniffUnknown,
+    /// Read the start of every file and only fall back to the extension when no signature matches.
+    /// Most accurate, but it opens every file.
+    SniffAll
+}
+
+/// What was scanned, when, and how. Kept alongside the tree so it can be saved with it.
+#[derive(Clone, Debug)]
+pub struct ScanInfo {
+    pub paths: Vec<PathBuf>,
+    pub scanned_at: SystemTime,
+    pub options: ScanOptions
+}
+
+impl ScanInfo {
+    pub fn new(paths: &[PathBuf], options: &ScanOptions) -> ScanInfo {
+        ScanInfo {
+            paths: paths.to_vec(),
+            scanned_at: SystemTime::now(),
+            options: options.clone()
+        }
+    }
+}
+
+/// One worker per CPU, which keeps the disk queue busy without oversubscribing the machine.
+pub fn default_thread_count() -> usize {
+    thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+}
+
+/// Running totals published by `read_dir` while a scan is in progress. Workers only bump
+/// atomic counters, so readers can poll `snapshot` as often as they like without slowing the walk.
+#[derive(Default)]
+pub struct ScanProgress {
+    files: AtomicU64,
+    directories: AtomicU64,
+    bytes: AtomicU64,
+    errors: AtomicU64,
+    current_path: Mutex<String>
+}
+
+/// A point-in-time copy of a `ScanProgress`.
+#[derive(Clone, Debug, Default)]
+pub struct ProgressSnapshot {
+    pub files: u64,
+    pub directories: u64,
+    pub bytes: u64,
+    pub errors: u64,
+    pub current_path: String
+}
+
+impl ScanProgress {
+    pub fn new() -> ScanProgress {
+        ScanProgress::default()
+    }
+
+    pub fn snapshot(&self) -> ProgressSnapshot {
+        ProgressSnapshot {
+            files: self.files.load(Ordering::Relaxed),
+            directories: self.directories.load(Ordering::Relaxed),
+            bytes: self.bytes.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+            current_path: self.current_path.lock().unwrap().clone()
+        }
+    }
+
+    fn add_file(&self) {
+        self.files.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn add_bytes(&self, size: u64) {
+        self.bytes.fetch_add(size, Ordering::Relaxed);
+    }
+
+    fn add_directory(&self, path: &Path) {
+        self.directories.fetch_add(1, Ordering::Relaxed);
+        // Whichever worker gets the lock wins, nobody waits just to update a label.
+        if let Ok(mut current_path) = self.current_path.try_lock() {
+            *current_path = path.to_string_lossy().into_owned();
+        }
+    }
+
+    fn add_error(&self) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Which of the two sizes recorded for every entry should be reported.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SizeMode {
+    /// The length of the file contents, as shown by `ls -l`.
+    Apparent,
+    /// The space actually reserved on disk, as shown by `du`.
+    Allocated
+}
+
 #[derive(Clone)]
 pub struct File {
-    name: String,
+    name: OsString,
     size: u64,
-    mime: String
+    allocated_size: u64,
+    mime: String,
+    hard_link: bool,
+    link_target: Option<PathBuf>,
+    modified: Option<SystemTime>
 }
 
+static SYMLINK_MIME: &str = "inode/symlink";
+
 impl File {
-    fn new(name: &str, size: u64, mime: &str) -> File {
+    pub(crate) fn new(name: &OsStr, size: u64, allocated_size: u64, mime: &str, hard_link: bool) -> File {
         File {
-            name: name.to_string(),
+            name: name.to_os_string(),
             size: size,
-            mime: mime.to_string()
+            allocated_size,
+            mime: mime.to_string(),
+            hard_link,
+            link_target: None,
+            modified: None
         }
     }
 
-    pub fn get_name(&self) -> &str {
+    /// A symbolic link that was not followed. It takes no space of its own.
+    fn new_link(name: &OsStr, target: PathBuf) -> File {
+        let mut file = File::new(name, 0, 0, SYMLINK_MIME, false);
+        file.set_link_target(Some(target));
+        file
+    }
+
+    /// The name for display, with any bytes that aren't valid UTF-8 replaced.
+    pub fn get_name(&self) -> Cow<'_, str> {
+        self.get_os_name().to_string_lossy()
+    }
+
+    /// The name exactly as the filesystem returned it.
+    pub fn get_os_name(&self) -> &OsStr {
         &self.name
     }
 
@@ -42,42 +273,104 @@ impl File {
         self.size
     }
 
+    pub fn get_allocated_size(&self) -> u64 {
+        self.allocated_size
+    }
+
+    pub fn get_size_for(&self, mode: SizeMode) -> u64 {
+        match mode {
+            SizeMode::Apparent => self.get_size(),
+            SizeMode::Allocated => self.get_allocated_size()
+        }
+    }
+
     pub fn get_mime(&self) -> &str {
         &self.mime
     }
+
+    /// True when another path to the same inode was seen first. That path owns the
+    /// size, so this entry reports a size of zero.
+    pub fn is_hard_link(&self) -> bool {
+        self.hard_link
+    }
+
+    /// Where the symbolic link this entry was found through points. Links that were followed
+    /// report the size of their target, the others are zero-sized.
+    pub fn get_link_target(&self) -> Option<&Path> {
+        self.link_target.as_deref()
+    }
+
+    pub fn get_modified(&self) -> Option<SystemTime> {
+        self.modified
+    }
+
+    pub(crate) fn set_link_target(&mut self, link_target: Option<PathBuf>) {
+        self.link_target = link_target;
+    }
+
+    pub(crate) fn set_modified(&mut self, modified: Option<SystemTime>) {
+        self.modified = modified;
+    }
 }
 
 impl fmt::Display for File {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{} {}", self.name, self.size)
+        write!(f, "{} {}", self.get_name(), self.size)
     }
 }
 
 #[derive(Clone)]
 pub struct Directory {
-    name: String,
+    name: OsString,
     size: u64,
+    allocated_size: u64,
     directories: Vec<Arc<Mutex<Directory>>>,
     files: Vec<File>,
     parent: Weak<Mutex<Directory>>,
-    path: String,
-    error: Option<ReadError>
+    path: PathBuf,
+    error: Option<ReadError>,
+    entry_errors: Vec<ReadError>,
+    mount_point: bool,
+    link_target: Option<PathBuf>,
+    modified: Option<SystemTime>,
+    /// Inodes of the files counted here that could also be reached through other links, so a rescan of
+    /// another part of the tree knows they're already counted.
+    counted_inodes: Vec<(u64, u64)>
 }
 
 impl Directory {
-    fn new(name: &str, parent: Weak<Mutex<Directory>>, path: &str) -> Directory {
+    pub(crate) fn new(name: &OsStr, parent: Weak<Mutex<Directory>>, path: &Path) -> Directory {
         Directory {
-            name: name.to_string(),
+            name: name.to_os_string(),
             size: 0,
+            allocated_size: 0,
             directories: vec![],
             files: vec![],
             parent: parent,
-            path: path.to_string(),
-            error: None
+            path: path.to_path_buf(),
+            error: None,
+            entry_errors: vec![],
+            mount_point: false,
+            link_target: None,
+            modified: None,
+            counted_inodes: vec![]
         }
     }
 
-    pub fn get_name(&self) -> &str {
+    /// A placeholder for another filesystem mounted below the scan root, which was not scanned.
+    fn new_mount_point(name: &OsStr, parent: Weak<Mutex<Directory>>, path: &Path) -> Directory {
+        let mut directory = Directory::new(name, parent, path);
+        directory.set_mount_point(true);
+        directory
+    }
+
+    /// The name for display, with any bytes that aren't valid UTF-8 replaced.
+    pub fn get_name(&self) -> Cow<'_, str> {
+        self.get_os_name().to_string_lossy()
+    }
+
+    /// The name exactly as the filesystem returned it.
+    pub fn get_os_name(&self) -> &OsStr {
         &self.name
     }
 
@@ -85,6 +378,17 @@ impl Directory {
         self.size
     }
 
+    pub fn get_allocated_size(&self) -> u64 {
+        self.allocated_size
+    }
+
+    pub fn get_size_for(&self, mode: SizeMode) -> u64 {
+        match mode {
+            SizeMode::Apparent => self.get_size(),
+            SizeMode::Allocated => self.get_allocated_size()
+        }
+    }
+
     pub fn get_subdirectories(&self) -> &Vec<Arc<Mutex<Directory>>> {
         &self.directories
     }
@@ -97,10 +401,26 @@ impl Directory {
         self.parent.clone()
     }
 
-    pub fn get_path(&self) -> &str {
+    pub fn get_path(&self) -> &Path {
         &self.path
     }
 
+    /// The full path of one of this directory's files.
+    pub fn get_file_path(&self, file: &File) -> PathBuf {
+        self.path.join(file.get_os_name())
+    }
+
+    /// The path for display, with any bytes that aren't valid UTF-8 replaced. A combined
+    /// root from `read_dirs` has no path, so its name is shown instead.
+    pub fn get_display_path(&self) -> Cow<'_, str> {
+        if self.path.as_os_str().is_empty() {
+            self.get_name()
+        }
+        else {
+            self.get_path().to_string_lossy()
+        }
+    }
+
     pub fn get_error(&self) -> &Option<ReadError> {
         &self.error
     }
@@ -109,108 +429,866 @@ impl Directory {
         self.error.is_some()
     }
 
-    fn set_subdirectories(&mut self, subdirs: Vec<Arc<Mutex<Directory>>>) {
+    /// Failures on individual entries of this directory. The entries were left out,
+    /// but the rest of the directory was still read.
+    pub fn get_entry_errors(&self) -> &Vec<ReadError> {
+        &self.entry_errors
+    }
+
+    /// True if this directory is a mount point that was skipped because the scan stayed
+    /// on one filesystem. It has no contents and a size of zero.
+    pub fn is_mount_point(&self) -> bool {
+        self.mount_point
+    }
+
+    /// Where the symbolic link this directory was reached through points, if it was followed.
+    pub fn get_link_target(&self) -> Option<&Path> {
+        self.link_target.as_deref()
+    }
+
+    pub fn get_modified(&self) -> Option<SystemTime> {
+        self.modified
+    }
+
+    pub(crate) fn set_subdirectories(&mut self, subdirs: Vec<Arc<Mutex<Directory>>>) {
         self.directories = subdirs;
     }
 
-    fn set_files(&mut self, files: Vec<File>) {
+    pub(crate) fn set_files(&mut self, files: Vec<File>) {
         self.files = files;
     }
 
-    fn set_size(&mut self, size: u64) {
+    pub(crate) fn set_size(&mut self, size: u64) {
         self.size = size;
     }
-    fn set_error(&mut self, error: Option<ReadError>) {
+
+    pub(crate) fn set_allocated_size(&mut self, allocated_size: u64) {
+        self.allocated_size = allocated_size;
+    }
+
+    pub(crate) fn set_error(&mut self, error: Option<ReadError>) {
         self.error = error;
     }
+
+    pub(crate) fn set_entry_errors(&mut self, errors: Vec<ReadError>) {
+        self.entry_errors = errors;
+    }
+
+    pub(crate) fn set_mount_point(&mut self, mount_point: bool) {
+        self.mount_point = mount_point;
+    }
+
+    pub(crate) fn set_link_target(&mut self, link_target: Option<PathBuf>) {
+        self.link_target = link_target;
+    }
+
+    pub(crate) fn set_modified(&mut self, modified: Option<SystemTime>) {
+        self.modified = modified;
+    }
 }
 
 impl fmt::Display for Directory {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let sub_strings = self.directories.iter().map(|ent| ent.lock().unwrap().to_string()).collect::<Vec<String>>().join("\n");
         let file_strings = self.files.iter().map(|ent| ent.to_string()).collect::<Vec<String>>().join("\n");
-        write!(f, "----- {} {} ------\n{}\n{}", self.name, self.size, sub_strings, file_strings)
+        write!(f, "----- {} {} ------\n{}\n{}", self.get_name(), self.size, sub_strings, file_strings)
     }
 }
 
-fn path_get_file_name(path: &PathBuf) -> Option<String> {
-    if let Some(osstr) = path.file_name() {
-        match osstr.to_os_string().into_string() {
-            Ok(file_name) => Some(file_name),
-            Err(_) => None
-        }
+#[cfg(unix)]
+fn get_allocated_size(metadata: &fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    // st_blocks is always counted in 512 byte units, whatever the filesystem block size is.
+    metadata.blocks() * 512
+}
+
+#[cfg(not(unix))]
+fn get_allocated_size(metadata: &fs::Metadata) -> u64 {
+    metadata.len()
+}
+
+#[cfg(unix)]
+fn get_inode_key(metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn get_inode_key(_metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+/// Identifies the inode behind a file that has more than one link, files with a single
+/// link can't be counted twice so they aren't tracked.
+#[cfg(unix)]
+fn get_hard_link_key(metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    if metadata.nlink() > 1 {
+        get_inode_key(metadata)
     }
     else {
         None
     }
 }
 
-impl From<std::io::Error> for ReadError {
-    fn from(error: std::io::Error) -> Self {
-        ReadError::IOError(error.kind())
-    }
+#[cfg(not(unix))]
+fn get_hard_link_key(_metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+#[cfg(unix)]
+fn get_device(metadata: &fs::Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(metadata.dev())
+}
+
+#[cfg(not(unix))]
+fn get_device(_metadata: &fs::Metadata) -> Option<u64> {
+    None
 }
 
-fn read_dir_inner(path: &PathBuf, cancel_checker: &Receiver<()>,
-                  directory: &Arc<Mutex<Directory>>, subdirectories: &mut Vec<Arc<Mutex<Directory>>>,
-                  files: &mut Vec<File>, size: &mut u64) -> Result<(), ReadError> {
-    for entry in fs::read_dir(&path)? {
+fn guess_mime(path: &Path, mime_detection: MimeDetection) -> String {
+    let from_extension = || mime_guess::from_path(path).first().map(|mime| mime.to_string());
+    // Files that can't be read, or that match no signature, are text if they look like it.
+    let from_contents = || match mime_sniff::sniff(path) {
+        Some(Sniffed { mime: Some(mime), .. }) => Some(mime.to_string()),
+        Some(Sniffed { is_text: false, .. }) => Some("application/octet-stream".to_string()),
+        _ => None
+    };
+    let mime = match mime_detection {
+        MimeDetection::Extension => from_extension(),
+        MimeDetection::SniffUnknown => from_extension().or_else(from_contents),
+        MimeDetection::SniffAll => match mime_sniff::sniff(path) {
+            Some(sniffed @ Sniffed { mime: Some(_), .. }) => match from_extension() {
+                // Only a real conflict overrides the extension, not a .docx file being a zip file.
+                Some(extension_mime) if sniffed.may_hold(&extension_mime) => Some(extension_mime),
+                _ => sniffed.mime.map(str::to_string)
+            },
+            Some(Sniffed { is_text: false, .. }) => from_extension().or_else(|| Some("application/octet-stream".to_string())),
+            _ => from_extension()
+        }
+    };
+    mime.unwrap_or_else(|| "text/plain".to_string())
+}
+
+/// State shared by every worker thread taking part in a scan.
+struct ScanContext<'a> {
+    cancel_checker: Mutex<Receiver<()>>,
+    cancelled: AtomicBool,
+    progress: &'a ScanProgress,
+    seen_inodes: Mutex<HashSet<(u64, u64)>>,
+    /// Set when the scan must stay on the devices the roots live on.
+    root_devices: Option<HashSet<u64>>,
+    symlinks: SymlinkPolicy,
+    mime_detection: MimeDetection,
+    /// The roots with every link resolved, to tell links into the scanned tree from links out of it.
+    canonical_roots: Vec<PathBuf>,
+    /// Directories entered through followed links so far.
+    visited_directories: Mutex<HashSet<(u64, u64)>>
+}
+
+impl<'a> ScanContext<'a> {
+    fn new(cancel_checker: Receiver<()>, progress: &'a ScanProgress, roots: &[PathBuf], options: &ScanOptions) -> ScanContext<'a> {
+        let root_metadata: Vec<fs::Metadata> = roots.iter().filter_map(|root| fs::metadata(root).ok()).collect();
+        let root_devices = if options.one_filesystem {
+            Some(root_metadata.iter().filter_map(get_device).collect())
+        }
+        else {
+            None
+        };
+
+        ScanContext {
+            cancel_checker: Mutex::new(cancel_checker),
+            cancelled: AtomicBool::new(false),
+            progress,
+            seen_inodes: Mutex::new(HashSet::new()),
+            root_devices,
+            symlinks: options.symlinks,
+            mime_detection: options.mime_detection,
+            canonical_roots: roots.iter().filter_map(|root| fs::canonicalize(root).ok()).collect(),
+            visited_directories: Mutex::new(HashSet::new())
+        }
+    }
+
+    /// Whether the directory a link at `path` leads to should be entered. Directories inside the roots are
+    /// scanned where they really are, whichever way the workers get to them first, and others only through
+    /// the first link to them, so nothing is counted twice and cycles end.
+    fn follow_directory_link(&self, path: &Path, metadata: &fs::Metadata) -> bool {
+        match fs::canonicalize(path) {
+            Ok(target) if self.canonical_roots.iter().any(|root| target.starts_with(root)) => false,
+            Ok(_) => match get_inode_key(metadata) {
+                Some(key) => self.visited_directories.lock().unwrap().insert(key),
+                None => true
+            },
+            Err(_) => false
+        }
+    }
+
+    fn is_other_filesystem(&self, metadata: &fs::Metadata) -> bool {
+        match &self.root_devices {
+            Some(root_devices) => matches!(get_device(metadata), Some(device) if !root_devices.contains(&device)),
+            None => false
+        }
+    }
+
+    /// The inode a file is tracked by so it's only counted once, None if it can't be reached twice.
+    fn get_tracked_inode(&self, metadata: &fs::Metadata) -> Option<(u64, u64)> {
+        // Once symlinks are followed any file can be reached twice, not just ones with several links.
+        if self.symlinks == SymlinkPolicy::Follow {
+            get_inode_key(metadata)
+        }
+        else {
+            get_hard_link_key(metadata)
+        }
+    }
+
+    /// Returns true if `inode` was already counted through another link, and marks it as counted otherwise.
+    fn is_known_hard_link(&self, inode: (u64, u64)) -> bool {
+        !self.seen_inodes.lock().unwrap().insert(inode)
+    }
+
+    fn is_cancelled(&self) -> bool {
+        if self.cancelled.load(Ordering::Relaxed) {
+            return true;
+        }
+
         // Normally this channel should be empty (which is an error, but one we expect)
         // However if we try to receive and there is no error, that means the user cancelled the scan.
-        if !cancel_checker.try_recv().is_err() {
+        // Only one worker needs to look at the channel, the others will see the flag.
+        if let Ok(receiver) = self.cancel_checker.try_lock() {
+            if receiver.try_recv().is_ok() {
+                self.cancelled.store(true, Ordering::Relaxed);
+            }
+        }
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// Everything `read_dir_inner` collects about one directory before it's stored.
+#[derive(Default)]
+struct DirectoryContents {
+    subdirectories: Vec<Arc<Mutex<Directory>>>,
+    files: Vec<File>,
+    size: u64,
+    allocated_size: u64,
+    errors: Vec<ReadError>,
+    counted_inodes: Vec<(u64, u64)>
+}
+
+fn read_dir_inner(path: &PathBuf, context: &ScanContext, directory: &Arc<Mutex<Directory>>,
+                  contents: &mut DirectoryContents) -> Result<(), ReadError> {
+    let mut subdirectory_paths: Vec<(PathBuf, Option<PathBuf>)> = Vec::new();
+    let entries = fs::read_dir(&path).map_err(|e| ReadError::new_io(path, Operation::OpenDirectory, e))?;
+    for entry in entries {
+        if context.is_cancelled() {
             return Err(ReadError::OperationCancelled);
         }
         
-        if let Ok(entry) = entry {
-            let metadata = entry.metadata()?;
-            *size += metadata.len();
-
-            if let Ok(name) = entry.file_name().into_string() {
-                if metadata.is_file() {
-                    let mime = mime_guess::from_path(entry.path()).first_or_text_plain()
-                                                                  .to_string();
-                    files.push(File::new(&name, metadata.len(), &mime)); 
-                }
-                else if metadata.is_dir() {
-                    let dir = read_dir_impl(&entry.path(), Arc::downgrade(&directory), &cancel_checker);
-                    if let Some(e) = dir.lock().unwrap().get_error() {
-                        if let ReadError::OperationCancelled = e {
-                            return Err(ReadError::OperationCancelled);
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                context.progress.add_error();
+                contents.errors.push(ReadError::new_io(path, Operation::ListDirectory, e));
+                continue;
+            }
+        };
+
+        let mut metadata = match entry.metadata() {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                context.progress.add_error();
+                contents.errors.push(ReadError::new_io(&entry.path(), Operation::Metadata, e));
+                continue;
+            }
+        };
+        let mut link_target = None;
+        if metadata.file_type().is_symlink() {
+            match context.symlinks {
+                SymlinkPolicy::Ignore => continue,
+                SymlinkPolicy::List => (),
+                SymlinkPolicy::Follow => {
+                    // Dangling links and links to directories that are scanned anyway are listed instead
+                    // of followed.
+                    if let Ok(target_metadata) = fs::metadata(entry.path()) {
+                        if !target_metadata.is_dir() || context.follow_directory_link(&entry.path(), &target_metadata) {
+                            metadata = target_metadata;
                         }
                     }
-                    *size += dir.lock().unwrap().size;
-                    subdirectories.push(dir);
                 }
             }
+            link_target = match fs::read_link(entry.path()) {
+                Ok(target) => Some(target),
+                Err(e) => {
+                    context.progress.add_error();
+                    contents.errors.push(ReadError::new_io(&entry.path(), Operation::ReadLink, e));
+                    Some(PathBuf::new())
+                }
+            };
         }
+
+        let inode = if metadata.is_file() { context.get_tracked_inode(&metadata) } else { None };
+        let hard_link = matches!(inode, Some(inode) if context.is_known_hard_link(inode));
+        if let (Some(inode), false) = (inode, hard_link) {
+            contents.counted_inodes.push(inode);
+        }
+        let (entry_size, entry_allocated_size) = if hard_link || metadata.file_type().is_symlink() {
+            (0, 0)
+        }
+        else {
+            (metadata.len(), get_allocated_size(&metadata))
+        };
+        contents.size += entry_size;
+        contents.allocated_size += entry_allocated_size;
+        context.progress.add_bytes(entry_size);
+
+        let name = entry.file_name();
+        if metadata.file_type().is_symlink() {
+            context.progress.add_file();
+            contents.files.push(File::new_link(&name, link_target.unwrap_or_default()));
+        }
+        else if metadata.is_file() {
+            let mime = guess_mime(&entry.path(), context.mime_detection);
+            context.progress.add_file();
+            let mut file = File::new(&name, entry_size, entry_allocated_size, &mime, hard_link);
+            file.set_link_target(link_target);
+            file.set_modified(metadata.modified().ok());
+            contents.files.push(file);
+        }
+        else if metadata.is_dir() {
+            if context.is_other_filesystem(&metadata) {
+                let mount_point = Directory::new_mount_point(&name, Arc::downgrade(directory), &entry.path());
+                contents.subdirectories.push(Arc::new(Mutex::new(mount_point)));
+            }
+            else {
+                subdirectory_paths.push((entry.path(), link_target));
+            }
+        }
+    }
+
+    // Sibling directories are handed to the thread pool, idle workers steal whatever is left.
+    let parent = Arc::downgrade(directory);
+    let scanned: Vec<Arc<Mutex<Directory>>> = subdirectory_paths.par_iter()
+        .map(|(subdirectory_path, link_target)| {
+            let dir = read_dir_impl(subdirectory_path, parent.clone(), context);
+            dir.lock().unwrap().set_link_target(link_target.clone());
+            dir
+        })
+        .collect();
+
+    add_scanned_subdirectories(scanned, contents)
+}
+
+fn add_scanned_subdirectories(scanned: Vec<Arc<Mutex<Directory>>>, contents: &mut DirectoryContents) -> Result<(), ReadError> {
+    for dir in scanned {
+        if let Some(ReadError::OperationCancelled) = dir.lock().unwrap().get_error() {
+            return Err(ReadError::OperationCancelled);
+        }
+        {
+            let unwrapped_dir = dir.lock().unwrap();
+            contents.size += unwrapped_dir.size;
+            contents.allocated_size += unwrapped_dir.allocated_size;
+        }
+        contents.subdirectories.push(dir);
     }
     Ok(())
 }
 
-fn read_dir_impl(path: &PathBuf, parent: Weak<Mutex<Directory>>, cancel_checker: &Receiver<()>) -> Arc<Mutex<Directory>> {
-    let root_name = match path_get_file_name(&path) {
-        Some(n) => n,
-        None => "".to_string()
-    };
+fn read_dir_impl(path: &PathBuf, parent: Weak<Mutex<Directory>>, context: &ScanContext) -> Arc<Mutex<Directory>> {
+    let root_name = path.file_name().unwrap_or_default();
 
-    let directory = Arc::new(Mutex::new(Directory::new(&root_name, parent, &path.to_string_lossy())));
-    let mut subdirectories: Vec<Arc<Mutex<Directory>>> = Vec::new();
-    let mut files: Vec<File> = Vec::new();
-    let mut size: u64 = 0;
-    let result = read_dir_inner(&path, &cancel_checker, &directory, &mut subdirectories, &mut files, &mut size);
+    context.progress.add_directory(path);
+    let mut new_directory = Directory::new(root_name, parent, path);
+    new_directory.set_modified(fs::metadata(path).and_then(|metadata| metadata.modified()).ok());
+    let directory = Arc::new(Mutex::new(new_directory));
+    let mut contents = DirectoryContents::default();
+    let result = read_dir_inner(&path, context, &directory, &mut contents);
 
     if let Ok(mut unwrapped_dir) = directory.lock() {
         if let Err(e) = result {
+            if let ReadError::IOError { .. } = e {
+                context.progress.add_error();
+            }
             unwrapped_dir.set_error(Some(e));
         }
-        unwrapped_dir.set_subdirectories(subdirectories);
-        unwrapped_dir.set_files(files);
-        unwrapped_dir.set_size(size);
+        unwrapped_dir.set_entry_errors(contents.errors);
+        unwrapped_dir.set_subdirectories(contents.subdirectories);
+        unwrapped_dir.set_files(contents.files);
+        unwrapped_dir.set_size(contents.size);
+        unwrapped_dir.set_allocated_size(contents.allocated_size);
+        unwrapped_dir.counted_inodes = contents.counted_inodes;
     }
 
     directory
 }
 
-pub fn read_dir(path: &PathBuf, cancel_checker: &Receiver<()>) -> Arc<Mutex<Directory>> {
-    read_dir_impl(path, Weak::new(), &cancel_checker)
+fn run_in_pool<T: Send>(threads: usize, scan: impl FnOnce() -> T + Send) -> T {
+    match rayon::ThreadPoolBuilder::new().num_threads(threads).build() {
+        Ok(pool) => pool.install(scan),
+        Err(_) => scan()
+    }
+}
+
+/// Scans `path` on a dedicated pool of `options.threads` workers. Sending a message on the
+/// channel paired with `cancel_checker` stops the scan with `ReadError::OperationCancelled`.
+/// Counters in `progress` are updated as the walk goes.
+pub fn read_dir(path: &PathBuf, cancel_checker: Receiver<()>, options: &ScanOptions,
+                progress: &ScanProgress) -> Arc<Mutex<Directory>> {
+    let context = ScanContext::new(cancel_checker, progress, std::slice::from_ref(path), options);
+    run_in_pool(options.threads, || read_dir_impl(path, Weak::new(), &context))
+}
+
+/// Like `read_dir`, but scans several paths at once. They become the subdirectories of a
+/// combined root, which has no path of its own and is named after the paths it holds.
+pub fn read_dirs(paths: &[PathBuf], cancel_checker: Receiver<()>, options: &ScanOptions,
+                 progress: &ScanProgress) -> Arc<Mutex<Directory>> {
+    if paths.len() == 1 {
+        return read_dir(&paths[0], cancel_checker, options, progress);
+    }
+
+    let context = ScanContext::new(cancel_checker, progress, paths, options);
+    let name = paths.iter().map(|path| path.to_string_lossy()).collect::<Vec<_>>().join(", ");
+    let root = Arc::new(Mutex::new(Directory::new(OsStr::new(&name), Weak::new(), Path::new(""))));
+    let parent = Arc::downgrade(&root);
+    let scanned = run_in_pool(options.threads, || {
+        paths.par_iter()
+             .map(|path| read_dir_impl(path, parent.clone(), &context))
+             .collect()
+    });
+
+    let mut contents = DirectoryContents::default();
+    let result = add_scanned_subdirectories(scanned, &mut contents);
+    if let Ok(mut unwrapped_root) = root.lock() {
+        unwrapped_root.set_error(result.err());
+        unwrapped_root.set_subdirectories(contents.subdirectories);
+        unwrapped_root.set_size(contents.size);
+        unwrapped_root.set_allocated_size(contents.allocated_size);
+    }
+    root
+}
+
+fn collect_errors_impl(dir: &Directory, errors: &mut Vec<ReadError>) {
+    if let Some(e @ ReadError::IOError { .. }) = dir.get_error() {
+        errors.push(e.clone());
+    }
+    errors.extend(dir.get_entry_errors().iter().cloned());
+    for subdir in dir.get_subdirectories() {
+        collect_errors_impl(&subdir.lock().unwrap(), errors);
+    }
+}
+
+/// Every read failure recorded anywhere under `dir`.
+pub fn collect_errors(dir: &Mutex<Directory>) -> Vec<ReadError> {
+    let mut errors = Vec::new();
+    collect_errors_impl(&dir.lock().unwrap(), &mut errors);
+    errors
+}
+
+/// Adds the given amounts to the sizes of `dir` and every directory above it, after something
+/// below `dir` has changed on disk.
+pub fn adjust_sizes(dir: &Arc<Mutex<Directory>>, size_change: i64, allocated_size_change: i64) {
+    let mut current = Some(dir.clone());
+    while let Some(dir) = current {
+        let mut unwrapped_dir = dir.lock().unwrap();
+        unwrapped_dir.size = (unwrapped_dir.size as i64 + size_change).max(0) as u64;
+        unwrapped_dir.allocated_size = (unwrapped_dir.allocated_size as i64 + allocated_size_change).max(0) as u64;
+        current = unwrapped_dir.parent.upgrade();
+    }
+}
+
+/// Takes the subdirectory called `name` out of `parent` once it has been deleted, and subtracts its size from every
+/// ancestor. Nothing changes if it's already gone.
+pub fn remove_subdirectory(parent: &Arc<Mutex<Directory>>, name: &OsStr) {
+    let removed = {
+        let mut unwrapped_parent = parent.lock().unwrap();
+        let index = unwrapped_parent.directories.iter().position(|subdir| subdir.lock().unwrap().name == name);
+        index.map(|index| unwrapped_parent.directories.remove(index))
+    };
+    if let Some(subdir) = removed {
+        let (size, allocated_size) = {
+            let unwrapped_subdir = subdir.lock().unwrap();
+            (unwrapped_subdir.size, unwrapped_subdir.allocated_size)
+        };
+        adjust_sizes(parent, -(size as i64), -(allocated_size as i64));
+    }
+}
+
+/// Takes the file called `name` out of `parent` once it has been deleted, and subtracts its size from every ancestor.
+/// Nothing changes if it's already gone.
+pub fn remove_file(parent: &Arc<Mutex<Directory>>, name: &OsStr) {
+    let removed = {
+        let mut unwrapped_parent = parent.lock().unwrap();
+        let index = unwrapped_parent.files.iter().position(|file| file.name == name);
+        index.map(|index| unwrapped_parent.files.remove(index))
+    };
+    if let Some(file) = removed {
+        adjust_sizes(parent, -(file.size as i64), -(file.allocated_size as i64));
+    }
+}
+
+fn collect_counted_inodes(dir: &Arc<Mutex<Directory>>, skipped: &Arc<Mutex<Directory>>, inodes: &mut HashSet<(u64, u64)>) {
+    if Arc::ptr_eq(dir, skipped) {
+        return;
+    }
+    let unwrapped_dir = dir.lock().unwrap();
+    inodes.extend(unwrapped_dir.counted_inodes.iter().cloned());
+    for subdir in unwrapped_dir.directories.iter() {
+        collect_counted_inodes(subdir, skipped, inodes);
+    }
+}
+
+/// Scans `dir` below `root` again, for swapping in with `replace_subdirectory` once it's done. The new
+/// directory has the same parent as `dir`. `paths` and `options` are those of the scan that found `root`,
+/// so files already counted elsewhere in the tree through other links stay uncounted, and the scan stays
+/// on the filesystems of the original paths.
+pub fn rescan_dir(root: &Arc<Mutex<Directory>>, dir: &Arc<Mutex<Directory>>, paths: &[PathBuf], cancel_checker: Receiver<()>,
+                  options: &ScanOptions, progress: &ScanProgress) -> Arc<Mutex<Directory>> {
+    let (path, parent, link_target) = {
+        let unwrapped_dir = dir.lock().unwrap();
+        (unwrapped_dir.path.clone(), unwrapped_dir.parent.clone(), unwrapped_dir.link_target.clone())
+    };
+    let mut counted_inodes = HashSet::new();
+    collect_counted_inodes(root, dir, &mut counted_inodes);
+    let context = ScanContext {
+        seen_inodes: Mutex::new(counted_inodes),
+        ..ScanContext::new(cancel_checker, progress, paths, options)
+    };
+    let new_dir = run_in_pool(options.threads, || read_dir_impl(&path, parent, &context));
+    new_dir.lock().unwrap().set_link_target(link_target);
+    new_dir
+}
+
+/// Puts `new_dir` in the place of `old_dir` below their parent, and adjusts the sizes of every ancestor
+/// by the difference. Returns false if `old_dir` is no longer part of the tree.
+pub fn replace_subdirectory(old_dir: &Arc<Mutex<Directory>>, new_dir: &Arc<Mutex<Directory>>) -> bool {
+    let parent = match old_dir.lock().unwrap().parent.upgrade() {
+        Some(parent) => parent,
+        None => return false
+    };
+    let (size_change, allocated_size_change) = {
+        let unwrapped_old_dir = old_dir.lock().unwrap();
+        let unwrapped_new_dir = new_dir.lock().unwrap();
+        (unwrapped_new_dir.size as i64 - unwrapped_old_dir.size as i64,
+         unwrapped_new_dir.allocated_size as i64 - unwrapped_old_dir.allocated_size as i64)
+    };
+
+    let replaced = {
+        let mut unwrapped_parent = parent.lock().unwrap();
+        match unwrapped_parent.directories.iter_mut().find(|subdir| Arc::ptr_eq(subdir, old_dir)) {
+            Some(subdir) => {
+                *subdir = new_dir.clone();
+                true
+            },
+            None => false
+        }
+    };
+    if replaced {
+        adjust_sizes(&parent, size_change, allocated_size_change);
+    }
+    replaced
+}
+
+/// Reads a single file the way the walker would have listed it. Returns None for directories and for
+/// links the options leave out. A file with more than one link is marked as a hard link, `update_file` decides
+/// whether it's counted.
+pub fn read_file(path: &Path, options: &ScanOptions) -> io::Result<Option<File>> {
+    let name = path.file_name().unwrap_or_default();
+    let mut metadata = fs::symlink_metadata(path)?;
+    if metadata.file_type().is_symlink() {
+        let target_metadata = match options.symlinks {
+            SymlinkPolicy::Ignore => return Ok(None),
+            SymlinkPolicy::List => None,
+            SymlinkPolicy::Follow => fs::metadata(path).ok()
+        };
+        match target_metadata {
+            Some(target_metadata) => metadata = target_metadata,
+            None => return Ok(Some(File::new_link(name, fs::read_link(path)?)))
+        }
+    }
+    if !metadata.is_file() {
+        return Ok(None);
+    }
+
+    let hard_link = get_hard_link_key(&metadata).is_some();
+    let mut file = File::new(name, metadata.len(), get_allocated_size(&metadata), &guess_mime(path, options.mime_detection), hard_link);
+    file.set_modified(metadata.modified().ok());
+    Ok(Some(file))
+}
+
+/// The directory at `path` below `root`, if it's part of the tree.
+pub fn find_directory(root: &Arc<Mutex<Directory>>, path: &Path) -> Option<Arc<Mutex<Directory>>> {
+    let mut current = root.clone();
+    loop {
+        let next = {
+            let unwrapped_current = current.lock().unwrap();
+            if unwrapped_current.path == path {
+                return Some(current.clone());
+            }
+            unwrapped_current.directories.iter()
+                             .find(|subdir| path.starts_with(&subdir.lock().unwrap().path))
+                             .cloned()
+        };
+        current = next?;
+    }
+}
+
+/// Adds `file` to `dir`, replacing any file with the same name, and adjusts the sizes of every ancestor.
+pub fn update_file(dir: &Arc<Mutex<Directory>>, mut file: File) {
+    let (size_change, allocated_size_change) = {
+        let mut unwrapped_dir = dir.lock().unwrap();
+        // A file with other links is only counted if it already was under this name, otherwise its size is
+        // likely counted elsewhere.
+        if file.hard_link {
+            if matches!(unwrapped_dir.files.iter().find(|other| other.name == file.name), Some(other) if !other.hard_link) {
+                file.hard_link = false;
+            }
+            else {
+                file.size = 0;
+                file.allocated_size = 0;
+            }
+        }
+        let change = (file.size as i64, file.allocated_size as i64);
+        match unwrapped_dir.files.iter_mut().find(|other| other.name == file.name) {
+            Some(other) => {
+                let change = (change.0 - other.size as i64, change.1 - other.allocated_size as i64);
+                *other = file;
+                change
+            },
+            None => {
+                unwrapped_dir.files.push(file);
+                change
+            }
+        }
+    };
+    adjust_sizes(dir, size_change, allocated_size_change);
+}
+
+/// Adds a separately scanned `subdir` below `parent`, replacing any subdirectory with the same name, and
+/// adjusts the sizes of every ancestor.
+pub fn add_subdirectory(parent: &Arc<Mutex<Directory>>, subdir: Arc<Mutex<Directory>>) {
+    let existing = {
+        let mut unwrapped_subdir = subdir.lock().unwrap();
+        unwrapped_subdir.parent = Arc::downgrade(parent);
+        let unwrapped_parent = parent.lock().unwrap();
+        unwrapped_parent.directories.iter()
+                        .find(|other| other.lock().unwrap().name == unwrapped_subdir.name)
+                        .cloned()
+    };
+
+    match existing {
+        Some(existing) => {
+            replace_subdirectory(&existing, &subdir);
+        },
+        None => {
+            let (size, allocated_size) = {
+                let unwrapped_subdir = subdir.lock().unwrap();
+                (unwrapped_subdir.size as i64, unwrapped_subdir.allocated_size as i64)
+            };
+            parent.lock().unwrap().directories.push(subdir);
+            adjust_sizes(parent, size, allocated_size);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("disk_analyzer-{}-{}", std::process::id(), name))
+    }
+
+    /// Creates an empty directory called `name` holding `files`, given as paths below it and their sizes.
+    fn create_temp_tree(name: &str, files: &[(&str, usize)]) -> PathBuf {
+        let root = get_temp_path(name);
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        for (path, size) in files {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, vec![0u8; *size]).unwrap();
+        }
+        root
+    }
+
+    fn scan(path: &Path, options: &ScanOptions) -> Arc<Mutex<Directory>> {
+        let (_cancel_sender, cancel_receiver) = std::sync::mpsc::channel();
+        read_dir(&path.to_path_buf(), cancel_receiver, options, &ScanProgress::new())
+    }
+
+    /// The type of a file called `name` that starts with `header`, with every file sniffed.
+    fn guess_sniffed_mime(name: &str, header: &[u8]) -> String {
+        let path = get_temp_path(name);
+        fs::write(&path, header).unwrap();
+        let mime = guess_mime(&path, MimeDetection::SniffAll);
+        fs::remove_file(&path).unwrap();
+        mime
+    }
+
+    #[test]
+    fn keeps_type_of_file_stored_in_container() {
+        assert_eq!(guess_sniffed_mime("report.docx", b"PK\x03\x04\x14\0\x06\0"),
+                   "application/vnd.openxmlformats-officedocument.wordprocessingml.document");
+        assert_eq!(guess_sniffed_mime("clip.ogv", b"OggS\0\x02\0\0"), "video/ogg");
+    }
+
+    #[test]
+    fn tells_brands_of_iso_media_files_apart() {
+        assert_eq!(guess_sniffed_mime("photo.heic", b"\0\0\0\x18ftypheic\0\0\0\0mif1heic"), "image/heic");
+        assert_eq!(guess_sniffed_mime("photo", b"\0\0\0\x18ftypheic\0\0\0\0mif1heic"), "image/heic");
+        assert_eq!(guess_sniffed_mime("movie", b"\0\0\0\x18ftypisom\0\0\0\0isomiso2"), "video/mp4");
+    }
+
+    #[test]
+    fn signature_overrides_conflicting_extension() {
+        assert_eq!(guess_sniffed_mime("notes.txt", b"PK\x03\x04\x14\0\x06\0"), "application/zip");
+        assert_eq!(guess_sniffed_mime("song.mp3", b"\x89PNG\r\n\x1a\n"), "image/png");
+    }
+
+    #[test]
+    fn requires_riff_header() {
+        assert_eq!(guess_sniffed_mime("sound", b"RIFF\x24\0\0\0WAVEfmt "), "audio/x-wav");
+        assert_eq!(guess_sniffed_mime("data", b"\0\0\0\0\0\0\0\0WAVEfmt "), "application/octet-stream");
+    }
+
+    #[test]
+    fn removing_an_entry_twice_subtracts_its_size_once() {
+        let path = create_temp_tree("remove-twice", &[("sub/a", 100), ("b", 50)]);
+        let root = scan(&path, &ScanOptions::default());
+        let size = root.lock().unwrap().get_size();
+        let subdir_size = root.lock().unwrap().get_subdirectories()[0].lock().unwrap().get_size();
+
+        remove_subdirectory(&root, OsStr::new("sub"));
+        remove_subdirectory(&root, OsStr::new("sub"));
+        assert_eq!(root.lock().unwrap().get_size(), size - subdir_size);
+        remove_file(&root, OsStr::new("b"));
+        remove_file(&root, OsStr::new("b"));
+        assert_eq!(root.lock().unwrap().get_size(), size - subdir_size - 50);
+        fs::remove_dir_all(&path).unwrap();
+    }
+
+    /// Rescans the subdirectory called `name` below `root` and swaps it in.
+    fn rescan_subdirectory(root: &Arc<Mutex<Directory>>, path: &Path, name: &str) {
+        let dir = root.lock().unwrap().get_subdirectories().iter()
+                      .find(|subdir| subdir.lock().unwrap().get_os_name() == name)
+                      .cloned()
+                      .unwrap();
+        let (_cancel_sender, cancel_receiver) = std::sync::mpsc::channel();
+        let new_dir = rescan_dir(root, &dir, &[path.to_path_buf()], cancel_receiver, &ScanOptions::default(),
+                                 &ScanProgress::new());
+        assert!(replace_subdirectory(&dir, &new_dir));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn hard_links_across_a_rescan_are_counted_once() {
+        let path = create_temp_tree("rescan-hard-link", &[("a/file", 1000), ("b/other", 10)]);
+        fs::hard_link(path.join("a/file"), path.join("b/link")).unwrap();
+        let root = scan(&path, &ScanOptions::default());
+        let size = root.lock().unwrap().get_size();
+
+        // Whichever of the two links was counted first, rescanning either side leaves the total alone.
+        rescan_subdirectory(&root, &path, "a");
+        assert_eq!(root.lock().unwrap().get_size(), size);
+        rescan_subdirectory(&root, &path, "b");
+        assert_eq!(root.lock().unwrap().get_size(), size);
+        fs::remove_dir_all(&path).unwrap();
+    }
+
+    fn get_subdirectory_names(dir: &Mutex<Directory>) -> Vec<String> {
+        let mut names: Vec<String> = dir.lock().unwrap().get_subdirectories().iter()
+                                        .map(|subdir| subdir.lock().unwrap().get_name().to_string())
+                                        .collect();
+        names.sort();
+        names
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn links_into_the_tree_are_listed_and_the_real_directory_is_scanned() {
+        let path = create_temp_tree("follow-inside", &[("real/file", 100)]);
+        for index in 0..8 {
+            std::os::unix::fs::symlink(path.join("real"), path.join(format!("link-{}", index))).unwrap();
+        }
+        let options = ScanOptions { symlinks: SymlinkPolicy::Follow, ..ScanOptions::default() };
+        // Which entry the workers get to first changes from run to run.
+        for _ in 0..20 {
+            let root = scan(&path, &options);
+            assert_eq!(get_subdirectory_names(&root), vec!["real"]);
+            assert_eq!(root.lock().unwrap().get_files().len(), 8);
+        }
+        fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn links_out_of_the_tree_are_followed_once() {
+        let outside = create_temp_tree("follow-outside-target", &[("file", 100)]);
+        let path = create_temp_tree("follow-outside", &[]);
+        std::os::unix::fs::symlink(&outside, path.join("first")).unwrap();
+        std::os::unix::fs::symlink(&outside, path.join("second")).unwrap();
+        std::os::unix::fs::symlink(&path, outside.join("back")).unwrap();
+        let options = ScanOptions { symlinks: SymlinkPolicy::Follow, ..ScanOptions::default() };
+        let root = scan(&path, &options);
+        let unwrapped_root = root.lock().unwrap();
+        assert_eq!(unwrapped_root.get_subdirectories().len(), 1);
+        assert_eq!(unwrapped_root.get_files().len(), 1);
+        // The link back to the root is listed inside the followed directory rather than entered.
+        let followed = unwrapped_root.get_subdirectories()[0].lock().unwrap();
+        assert!(followed.get_subdirectories().is_empty());
+        assert_eq!(followed.get_files().len(), 2);
+        fs::remove_dir_all(&path).unwrap();
+        fs::remove_dir_all(&outside).unwrap();
+    }
+
+    /// Every file below `dir` with its size and whether it's marked as a hard link.
+    fn collect_file_sizes(dir: &Mutex<Directory>, files: &mut Vec<(u64, bool)>) {
+        let unwrapped_dir = dir.lock().unwrap();
+        files.extend(unwrapped_dir.get_files().iter().map(|file| (file.get_size(), file.is_hard_link())));
+        for subdir in unwrapped_dir.get_subdirectories() {
+            collect_file_sizes(subdir, files);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn hard_linked_files_are_counted_once() {
+        let path = create_temp_tree("hard-links", &[("a/file", 1000), ("single", 10)]);
+        fs::create_dir(path.join("b")).unwrap();
+        fs::hard_link(path.join("a/file"), path.join("a/same-directory")).unwrap();
+        fs::hard_link(path.join("a/file"), path.join("b/other-directory")).unwrap();
+
+        // The links are found by different workers, in any order.
+        for _ in 0..20 {
+            let root = scan(&path, &ScanOptions::default());
+            let mut files = Vec::new();
+            collect_file_sizes(&root, &mut files);
+            files.sort();
+            assert_eq!(files, vec![(0, true), (0, true), (10, false), (1000, false)]);
+            let counted_inodes: usize = root.lock().unwrap().get_subdirectories().iter()
+                                            .map(|subdir| subdir.lock().unwrap().counted_inodes.len())
+                                            .sum();
+            assert_eq!(counted_inodes, 1);
+            assert!(root.lock().unwrap().counted_inodes.is_empty());
+        }
+        fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn files_reached_through_followed_links_are_counted_once() {
+        let path = create_temp_tree("follow-file", &[("file", 1000)]);
+        std::os::unix::fs::symlink(path.join("file"), path.join("link")).unwrap();
+        let options = ScanOptions { symlinks: SymlinkPolicy::Follow, ..ScanOptions::default() };
+        let mut files = Vec::new();
+        collect_file_sizes(&scan(&path, &options), &mut files);
+        files.sort();
+        assert_eq!(files, vec![(0, true), (1000, false)]);
+
+        let mut files = Vec::new();
+        collect_file_sizes(&scan(&path, &ScanOptions::default()), &mut files);
+        files.sort();
+        // Listed links take no space of their own.
+        assert_eq!(files, vec![(0, false), (1000, false)]);
+        fs::remove_dir_all(&path).unwrap();
+    }
 }
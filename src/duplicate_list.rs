@@ -0,0 +1,104 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use gtk::prelude::*;
+use std::cell::RefCell;
+use std::path::Path;
+use std::rc::Rc;
+use super::duplicates::{DuplicateFile, DuplicateSet};
+use super::list_format;
+
+/// Value of the file column for the rows of whole sets.
+const SET_ROW: i64 = -1;
+
+fn get_index(model: &gtk::TreeModel, iter: &gtk::TreeIter, id: i32) -> i64 {
+    model.get_value(iter, id).get::<i64>()
+        .expect("Couldn't get index from tree model")
+        .expect("Couldn't get index from tree model")
+}
+
+fn fill_duplicate_store(store: &gtk::TreeStore, sets: &[DuplicateSet]) {
+    let columns = [0, 1, 2, 3, 4, 5];
+    for (set_index, set) in sets.iter().enumerate() {
+        let name = format!("{} copies of {}", set.files.len(), set.files[0].name.to_string_lossy());
+        let set_iter = store.insert_with_values(None, None, &columns, &[&list_format::FOLDER_ICON, &name, &set.size,
+                                                                        &set.get_wasted_size(), &(set_index as i64), &SET_ROW]);
+        for (file_index, file) in set.files.iter().enumerate() {
+            store.insert_with_values(Some(&set_iter), None, &columns, &[&file.mime, &file.path.to_string_lossy().as_ref(),
+                                                                        &set.size, &0u64, &(set_index as i64),
+                                                                        &(file_index as i64)]);
+        }
+    }
+}
+
+/// Sets of identical files, each of which expands to list its copies.
+pub struct DuplicateList {
+    tree: gtk::TreeView,
+    store: gtk::TreeStore,
+    sets: Rc<RefCell<Vec<DuplicateSet>>>
+}
+
+impl DuplicateList {
+    pub fn new() -> DuplicateList {
+        // icon, name or path, size of each copy, wasted size, set index, file index
+        let store = gtk::TreeStore::new(&[String::static_type(), String::static_type(), u64::static_type(), u64::static_type(),
+                                          i64::static_type(), i64::static_type()]);
+        let tree = gtk::TreeView::with_model(&store);
+        list_format::add_icon_column(&tree, 0);
+        list_format::add_column(&tree, 1, "Name", None, false, gtk::CellRendererText::new());
+        list_format::add_size_column(&tree, 2, "Size");
+        list_format::add_size_column(&tree, 3, "Wasted");
+
+        DuplicateList { tree, store, sets: Rc::new(RefCell::new(Vec::new())) }
+    }
+
+    pub fn get_widget(&self) -> &gtk::TreeView {
+        &self.tree
+    }
+
+    pub fn set_duplicates(&self, sets: Vec<DuplicateSet>) {
+        self.store.clear();
+        fill_duplicate_store(&self.store, &sets);
+        *self.sets.borrow_mut() = sets;
+    }
+
+    /// The total space that could be freed.
+    pub fn get_wasted_size(&self) -> u64 {
+        self.sets.borrow().iter().map(DuplicateSet::get_wasted_size).sum()
+    }
+
+    pub fn get_set_count(&self) -> usize {
+        self.sets.borrow().len()
+    }
+
+    /// The copy in the row at `path`. None for the rows of whole sets.
+    pub fn get_file(&self, path: &gtk::TreePath) -> Option<DuplicateFile> {
+        let iter = self.store.get_iter(path)?;
+        let set_index = get_index(self.store.upcast_ref(), &iter, 4);
+        let file_index = get_index(self.store.upcast_ref(), &iter, 5);
+        if file_index == SET_ROW {
+            return None;
+        }
+        Some(self.sets.borrow()[set_index as usize].files[file_index as usize].clone())
+    }
+
+    pub fn get_selected_file(&self) -> Option<DuplicateFile> {
+        let (model, iter) = self.tree.get_selection().get_selected()?;
+        self.get_file(&model.get_path(&iter)?)
+    }
+
+    /// Forgets copies that were removed from disk, either themselves or with a directory they were in. Sets that
+    /// are down to one copy are dropped.
+    pub fn remove_files(&self, paths: &[&Path]) {
+        let mut sets = self.sets.borrow_mut();
+        for set in sets.iter_mut() {
+            set.files.retain(|file| !paths.iter().any(|path| file.path.starts_with(path)));
+        }
+        sets.retain(|set| set.files.len() > 1);
+        sets.sort_by_key(|set| std::cmp::Reverse(set.get_wasted_size()));
+
+        self.store.clear();
+        fill_duplicate_store(&self.store, &sets);
+    }
+}
@@ -0,0 +1,212 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use super::dir_walker;
+
+/// How much is read from each end of a file for the partial hash. Files up to twice this size are
+/// read completely by the partial hash, so they don't need a full one.
+const BLOCK_SIZE: u64 = 4096;
+
+/// One copy of a duplicated file, with the directory it's listed in so it can be taken out of the tree.
+#[derive(Clone)]
+pub struct DuplicateFile {
+    pub parent: Arc<Mutex<dir_walker::Directory>>,
+    pub name: OsString,
+    pub path: PathBuf,
+    pub mime: String
+}
+
+/// Files with identical contents.
+pub struct DuplicateSet {
+    /// The size of each copy.
+    pub size: u64,
+    pub files: Vec<DuplicateFile>
+}
+
+impl DuplicateSet {
+    /// The space that would be freed by keeping only one copy.
+    pub fn get_wasted_size(&self) -> u64 {
+        self.size * (self.files.len() as u64).saturating_sub(1)
+    }
+}
+
+/// Groups every regular file below `dir` by size. Empty files, links and hard links are left out,
+/// since removing them frees nothing.
+fn collect_files(dir: &Arc<Mutex<dir_walker::Directory>>, by_size: &mut HashMap<u64, Vec<DuplicateFile>>) {
+    let unwrapped_dir = dir.lock().unwrap();
+    for file in unwrapped_dir.get_files() {
+        if file.get_size() == 0 || file.get_link_target().is_some() || file.is_hard_link() {
+            continue;
+        }
+        by_size.entry(file.get_size()).or_default().push(DuplicateFile {
+            parent: dir.clone(),
+            name: file.get_os_name().to_os_string(),
+            path: unwrapped_dir.get_file_path(file),
+            mime: file.get_mime().to_string()
+        });
+    }
+    for subdir in unwrapped_dir.get_subdirectories() {
+        collect_files(subdir, by_size);
+    }
+}
+
+/// Hashes the first and last blocks of a file that's `size` bytes long.
+fn hash_ends(path: &Path, size: u64) -> io::Result<blake3::Hash> {
+    let mut file = fs::File::open(path)?;
+    let mut buffer = Vec::with_capacity((2 * BLOCK_SIZE).min(size) as usize);
+    (&mut file).take(BLOCK_SIZE).read_to_end(&mut buffer)?;
+    if size > 2 * BLOCK_SIZE {
+        file.seek(SeekFrom::Start(size - BLOCK_SIZE))?;
+    }
+    file.take(BLOCK_SIZE).read_to_end(&mut buffer)?;
+    Ok(blake3::hash(&buffer))
+}
+
+fn hash_contents(path: &Path) -> io::Result<blake3::Hash> {
+    let mut hasher = blake3::Hasher::new();
+    io::copy(&mut fs::File::open(path)?, &mut hasher)?;
+    Ok(hasher.finalize())
+}
+
+/// Splits `files` into groups with the same hash, dropping groups of one. Files that can't be read
+/// anymore are left out.
+fn split_by_hash(files: Vec<DuplicateFile>, hash: impl Fn(&Path) -> io::Result<blake3::Hash> + Sync) -> Vec<Vec<DuplicateFile>> {
+    let hashed: Vec<(blake3::Hash, DuplicateFile)> = files.into_par_iter()
+        .filter_map(|file| hash(&file.path).ok().map(|hash| (hash, file)))
+        .collect();
+
+    let mut by_hash: HashMap<blake3::Hash, Vec<DuplicateFile>> = HashMap::new();
+    for (hash, file) in hashed {
+        by_hash.entry(hash).or_default().push(file);
+    }
+    by_hash.into_values().filter(|group| group.len() > 1).collect()
+}
+
+/// Finds the files below `root` whose contents are identical, largest waste first. Files are compared by
+/// size, then by a hash of their ends, and only the ones still alike after that are read in full.
+pub fn find_duplicates(root: &Arc<Mutex<dir_walker::Directory>>) -> Vec<DuplicateSet> {
+    let mut by_size = HashMap::new();
+    collect_files(root, &mut by_size);
+
+    let mut sets: Vec<DuplicateSet> = by_size.into_par_iter()
+        .filter(|(_, files)| files.len() > 1)
+        .flat_map_iter(|(size, files)| {
+            split_by_hash(files, |path| hash_ends(path, size)).into_iter()
+                .flat_map(move |group| {
+                    if size <= 2 * BLOCK_SIZE {
+                        vec![group]
+                    }
+                    else {
+                        split_by_hash(group, hash_contents)
+                    }
+                })
+                .map(move |files| DuplicateSet { size, files })
+        })
+        .collect();
+
+    sets.sort_by_key(|set| std::cmp::Reverse(set.get_wasted_size()));
+    for set in sets.iter_mut() {
+        set.files.sort_by(|first, second| first.path.cmp(&second.path));
+    }
+    sets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates an empty directory called `name` holding `files`, given as names and contents.
+    fn create_files(name: &str, files: &[(&str, Vec<u8>)]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("disk_analyzer-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        for (name, contents) in files {
+            fs::write(path.join(name), contents).unwrap();
+        }
+        path
+    }
+
+    fn scan(path: &PathBuf) -> Arc<Mutex<dir_walker::Directory>> {
+        let (_cancel_sender, cancel_receiver) = std::sync::mpsc::channel();
+        dir_walker::read_dir(path, cancel_receiver, &dir_walker::ScanOptions::default(), &dir_walker::ScanProgress::new())
+    }
+
+    fn scan_files(name: &str, files: &[(&str, Vec<u8>)]) -> (PathBuf, Arc<Mutex<dir_walker::Directory>>) {
+        let path = create_files(name, files);
+        let root = scan(&path);
+        (path, root)
+    }
+
+    fn get_names(set: &DuplicateSet) -> Vec<String> {
+        set.files.iter().map(|file| file.name.to_string_lossy().into_owned()).collect()
+    }
+
+    /// A file longer than both blocks of the partial hash, with `middle` in between them.
+    fn long_contents(middle: u8) -> Vec<u8> {
+        let mut contents = vec![1u8; 3 * BLOCK_SIZE as usize];
+        contents[BLOCK_SIZE as usize + 10] = middle;
+        contents
+    }
+
+    #[test]
+    fn files_of_different_sizes_are_not_compared() {
+        let (path, root) = scan_files("duplicates-sizes", &[("a", vec![7; 100]), ("b", vec![7; 101])]);
+        assert!(find_duplicates(&root).is_empty());
+        fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn files_of_the_same_size_with_different_contents_are_not_duplicates() {
+        let (path, root) = scan_files("duplicates-contents", &[("a", vec![7; 100]), ("b", vec![8; 100])]);
+        assert!(find_duplicates(&root).is_empty());
+        fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn files_that_differ_between_their_ends_are_not_duplicates() {
+        let (path, root) = scan_files("duplicates-middle", &[("a", long_contents(2)), ("b", long_contents(3))]);
+        let size = 3 * BLOCK_SIZE;
+        // The partial hash can't tell them apart, only reading them in full does.
+        assert_eq!(hash_ends(&path.join("a"), size).unwrap(), hash_ends(&path.join("b"), size).unwrap());
+        assert!(find_duplicates(&root).is_empty());
+        fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn identical_files_are_found_largest_waste_first() {
+        let (path, root) = scan_files("duplicates-identical", &[
+            ("small-1", vec![7; 100]),
+            ("small-2", vec![7; 100]),
+            ("long-1", long_contents(2)),
+            ("long-2", long_contents(2)),
+            ("long-3", long_contents(2)),
+            ("long-other", long_contents(3)),
+            ("empty-1", Vec::new()),
+            ("empty-2", Vec::new())
+        ]);
+        let sets = find_duplicates(&root);
+        assert_eq!(sets.len(), 2);
+        assert_eq!(get_names(&sets[0]), vec!["long-1", "long-2", "long-3"]);
+        assert_eq!(sets[0].get_wasted_size(), 6 * BLOCK_SIZE);
+        assert_eq!(get_names(&sets[1]), vec!["small-1", "small-2"]);
+        assert_eq!(sets[1].get_wasted_size(), 100);
+        fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn hard_links_are_not_duplicates() {
+        let path = create_files("duplicates-hard-link", &[("a", vec![7; 100])]);
+        fs::hard_link(path.join("a"), path.join("b")).unwrap();
+        assert!(find_duplicates(&scan(&path)).is_empty());
+        fs::remove_dir_all(&path).unwrap();
+    }
+}
@@ -0,0 +1,68 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use gtk::prelude::*;
+use super::dir_walker::{ErrorCategory, ReadError};
+
+static CATEGORIES: [ErrorCategory; 3] = [ErrorCategory::PermissionDenied, ErrorCategory::NotFound, ErrorCategory::Other];
+
+fn fill_error_store(store: &gtk::TreeStore, errors: &[ReadError]) {
+    for category in CATEGORIES.iter() {
+        let in_category: Vec<&ReadError> = errors.iter().filter(|e| e.get_category() == *category).collect();
+        if in_category.is_empty() {
+            continue;
+        }
+
+        let title = format!("{} ({})", category, in_category.len());
+        let category_iter = store.insert_with_values(None, None, &[0, 1, 2], &[&title, &"", &""]);
+        for error in in_category {
+            if let ReadError::IOError { path, operation, message, .. } = error {
+                store.insert_with_values(Some(&category_iter), None, &[0, 1, 2],
+                                         &[&path.to_string_lossy().as_ref(), &operation.to_string(), &message]);
+            }
+        }
+    }
+}
+
+fn add_text_column(tree: &gtk::TreeView, id: i32, title: &str) {
+    let column = gtk::TreeViewColumn::new();
+    let cell = gtk::CellRendererText::new();
+    column.pack_start(&cell, true);
+    column.set_title(title);
+    column.set_resizable(true);
+    column.add_attribute(&cell, "text", id);
+    tree.append_column(&column);
+}
+
+/// A list of every path that couldn't be read, grouped by the kind of failure.
+pub struct ErrorList {
+    scrolled: gtk::ScrolledWindow,
+    tree: gtk::TreeView,
+    store: gtk::TreeStore
+}
+
+impl ErrorList {
+    pub fn new() -> ErrorList {
+        let store = gtk::TreeStore::new(&[String::static_type(), String::static_type(), String::static_type()]);
+        let tree = gtk::TreeView::with_model(&store);
+        add_text_column(&tree, 0, "Path");
+        add_text_column(&tree, 1, "Operation");
+        add_text_column(&tree, 2, "Error");
+
+        let scrolled = gtk::ScrolledWindow::new::<gtk::Adjustment, gtk::Adjustment>(None, None);
+        scrolled.add(&tree);
+        scrolled.set_vexpand(true);
+        ErrorList { scrolled, tree, store }
+    }
+
+    pub fn get_widget(&self) -> &gtk::ScrolledWindow {
+        &self.scrolled
+    }
+
+    pub fn set_errors(&self, errors: &[ReadError]) {
+        self.store.clear();
+        fill_error_store(&self.store, errors);
+        self.tree.expand_all();
+    }
+}
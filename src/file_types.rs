@@ -0,0 +1,182 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use super::dir_walker::{Directory, SizeMode};
+use super::largest::Largest;
+
+/// The mime types of archives and compressed files, whether guessed from the extension or sniffed.
+/// Listed explicitly because formats that merely use a container, like "application/epub+zip", aren't archives.
+static ARCHIVE_MIME_TYPES: &[&str] = &[
+    "application/gzip", "application/java-archive", "application/vnd.ms-cab-compressed", "application/vnd.rar",
+    "application/x-7z-compressed", "application/x-ace-compressed", "application/x-archive", "application/x-bzip",
+    "application/x-bzip2", "application/x-compress", "application/x-compressed", "application/x-cpio",
+    "application/x-gtar", "application/x-gzip", "application/x-lzh-compressed", "application/x-lzip",
+    "application/x-lzma", "application/x-rar-compressed", "application/x-tar", "application/x-ustar",
+    "application/x-xz", "application/x-zip-compressed", "application/zip", "application/zstd"
+];
+
+/// A rough grouping of mime types.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Category {
+    Video,
+    Image,
+    Audio,
+    Archive,
+    Text,
+    Application,
+    Other
+}
+
+impl Category {
+    /// The category of a mime type. Archives are picked out of "application".
+    pub fn of(mime: &str) -> Category {
+        if ARCHIVE_MIME_TYPES.contains(&mime) {
+            return Category::Archive;
+        }
+        match mime.split('/').next().unwrap_or("") {
+            "video" => Category::Video,
+            "image" => Category::Image,
+            "audio" => Category::Audio,
+            "text" => Category::Text,
+            "application" => Category::Application,
+            _ => Category::Other
+        }
+    }
+
+    /// The color files of this category are drawn in, as red, green and blue between 0 and 1.
+    pub fn get_color(self) -> (f64, f64, f64) {
+        match self {
+            Category::Video => (0.85, 0.55, 0.20),
+            Category::Image => (0.45, 0.70, 0.35),
+            Category::Audio => (0.60, 0.45, 0.75),
+            Category::Archive => (0.80, 0.36, 0.36),
+            Category::Text => (0.35, 0.55, 0.80),
+            Category::Application => (0.80, 0.75, 0.35),
+            Category::Other => (0.65, 0.65, 0.65)
+        }
+    }
+}
+
+impl fmt::Display for Category {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = match self {
+            Category::Video => "Video",
+            Category::Image => "Images",
+            Category::Audio => "Audio",
+            Category::Archive => "Archives",
+            Category::Text => "Text",
+            Category::Application => "Applications and data",
+            Category::Other => "Other"
+        };
+        write!(f, "{}", description)
+    }
+}
+
+#[derive(Clone, Copy, Default, Debug)]
+pub struct TypeTotals {
+    pub size: u64,
+    pub count: u64
+}
+
+/// How much space each kind of file takes up, biggest first.
+pub struct TypeBreakdown {
+    pub categories: Vec<(Category, TypeTotals)>,
+    pub mime_types: Vec<(String, TypeTotals)>
+}
+
+fn add_mime_totals(dir: &Directory, size_mode: SizeMode, totals: &mut HashMap<String, TypeTotals>) {
+    for file in dir.get_files() {
+        let entry = match totals.get_mut(file.get_mime()) {
+            Some(entry) => entry,
+            None => totals.entry(file.get_mime().to_string()).or_default()
+        };
+        entry.size += file.get_size_for(size_mode);
+        entry.count += 1;
+    }
+    for subdir in dir.get_subdirectories() {
+        add_mime_totals(&subdir.lock().unwrap(), size_mode, totals);
+    }
+}
+
+/// Adds up the files below `dir` by mime type and by category.
+pub fn get_breakdown(dir: &Mutex<Directory>, size_mode: SizeMode) -> TypeBreakdown {
+    let mut mime_totals = HashMap::new();
+    add_mime_totals(&dir.lock().unwrap(), size_mode, &mut mime_totals);
+
+    let mut category_totals: HashMap<Category, TypeTotals> = HashMap::new();
+    for (mime, totals) in mime_totals.iter() {
+        let entry = category_totals.entry(Category::of(mime)).or_default();
+        entry.size += totals.size;
+        entry.count += totals.count;
+    }
+
+    let mut categories: Vec<(Category, TypeTotals)> = category_totals.into_iter().collect();
+    categories.sort_by_key(|(_, totals)| std::cmp::Reverse(totals.size));
+    let mut mime_types: Vec<(String, TypeTotals)> = mime_totals.into_iter().collect();
+    mime_types.sort_by_key(|(_, totals)| std::cmp::Reverse(totals.size));
+    TypeBreakdown { categories, mime_types }
+}
+
+fn add_largest_files(dir: &Directory, filter: &dyn Fn(&str) -> bool, size_mode: SizeMode,
+                     largest: &mut Largest<(PathBuf, String)>) {
+    for file in dir.get_files() {
+        let size = file.get_size_for(size_mode);
+        if largest.accepts(size) && filter(file.get_mime()) {
+            largest.push(size, (dir.get_file_path(file), file.get_mime().to_string()));
+        }
+    }
+    for subdir in dir.get_subdirectories() {
+        add_largest_files(&subdir.lock().unwrap(), filter, size_mode, largest);
+    }
+}
+
+/// The size, path and mime type of the `count` largest files below `dir` whose mime type passes `filter`,
+/// biggest first.
+pub fn get_largest_files(dir: &Mutex<Directory>, filter: &dyn Fn(&str) -> bool, count: usize,
+                         size_mode: SizeMode) -> Vec<(u64, (PathBuf, String))> {
+    let mut largest = Largest::new(count);
+    add_largest_files(&dir.lock().unwrap(), filter, size_mode, &mut largest);
+    largest.into_sorted_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn archives_are_picked_out_of_applications() {
+        for mime in &["application/zip", "application/x-gzip", "application/gzip", "application/x-compressed",
+                      "application/x-tar", "application/x-bzip2", "application/x-xz", "application/zstd",
+                      "application/x-7z-compressed", "application/x-rar-compressed", "application/java-archive"] {
+            assert_eq!(Category::of(mime), Category::Archive, "{}", mime);
+        }
+    }
+
+    #[test]
+    fn formats_stored_in_archives_are_not_archives() {
+        assert_eq!(Category::of("application/epub+zip"), Category::Application);
+        assert_eq!(Category::of("application/vnd.airzip.filesecure.azf"), Category::Application);
+        assert_eq!(Category::of("application/x-safari-webarchive"), Category::Application);
+        assert_eq!(Category::of("application/vnd.openxmlformats-officedocument.wordprocessingml.document"),
+                   Category::Application);
+        assert_eq!(Category::of("image/svg+xml"), Category::Image);
+        assert_eq!(Category::of("text/x-tarball-notes"), Category::Text);
+    }
+
+    #[test]
+    fn other_types_go_by_their_media_type() {
+        assert_eq!(Category::of("video/mp4"), Category::Video);
+        assert_eq!(Category::of("image/png"), Category::Image);
+        assert_eq!(Category::of("audio/x-wav"), Category::Audio);
+        assert_eq!(Category::of("text/plain"), Category::Text);
+        assert_eq!(Category::of("application/pdf"), Category::Application);
+        assert_eq!(Category::of("inode/x-empty"), Category::Other);
+        assert_eq!(Category::of("application"), Category::Application);
+        assert_eq!(Category::of(""), Category::Other);
+    }
+}
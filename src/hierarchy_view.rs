@@ -0,0 +1,207 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use gtk::prelude::*;
+use std::cell::RefCell;
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex, Weak};
+use super::dir_walker;
+use super::list_format::{self, CellDataFunc};
+
+/// Value of the id column for file rows.
+const FILE_ID: i64 = -1;
+/// Value of the id column for the empty row that stands in for the children of a directory until it's expanded.
+const PLACEHOLDER_ID: i64 = -2;
+
+struct HierarchyState {
+    /// Every directory that has a row, indexed by the id column of its row.
+    directories: Vec<Weak<Mutex<dir_walker::Directory>>>,
+    root_size: u64,
+    size_mode: dir_walker::SizeMode
+}
+
+fn get_percentage(size: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    }
+    else {
+        size as f64 / total as f64 * 100.0
+    }
+}
+
+fn get_id(model: &gtk::TreeModel, iter: &gtk::TreeIter) -> i64 {
+    model.get_value(iter, 5).get::<i64>()
+        .expect("Couldn't get id from tree model")
+        .expect("Couldn't get id from tree model")
+}
+
+fn get_entry_path(model: &gtk::TreeModel, iter: &gtk::TreeIter) -> String {
+    model.get_value(iter, 6).get::<String>()
+        .expect("Couldn't get path from tree model")
+        .unwrap_or_default()
+}
+
+/// Adds a row for every entry of `dir` below `parent`. Subdirectories only get a placeholder child,
+/// which is replaced with their real contents when they're first expanded.
+fn insert_children(store: &gtk::TreeStore, parent: Option<&gtk::TreeIter>, dir: &Mutex<dir_walker::Directory>,
+                   state: &mut HierarchyState) {
+    let columns = [0, 1, 2, 3, 4, 5, 6];
+    let unwrapped_dir = dir.lock().unwrap();
+    let dir_size = unwrapped_dir.get_size_for(state.size_mode);
+    for subdir in unwrapped_dir.get_subdirectories() {
+        let unwrapped_subdir = subdir.lock().unwrap();
+        let icon = list_format::get_directory_icon(&unwrapped_subdir);
+        let name = list_format::get_display_name(&unwrapped_subdir.get_name(), unwrapped_subdir.get_link_target());
+        let size = unwrapped_subdir.get_size_for(state.size_mode);
+        let id = state.directories.len() as i64;
+        state.directories.push(Arc::downgrade(subdir));
+
+        let path = unwrapped_subdir.get_path().to_string_lossy();
+        let iter = store.insert_with_values(parent, None, &columns, &[&icon, &name, &size, &get_percentage(size, dir_size),
+                                                                       &get_percentage(size, state.root_size), &id,
+                                                                       &path.as_ref()]);
+        if !unwrapped_subdir.get_subdirectories().is_empty() || !unwrapped_subdir.get_files().is_empty() {
+            store.insert_with_values(Some(&iter), None, &columns, &[&"", &"", &0u64, &0.0, &0.0, &PLACEHOLDER_ID, &""]);
+        }
+    }
+    for file in unwrapped_dir.get_files() {
+        let icon = list_format::get_file_icon(file);
+        let name = list_format::get_display_name(&file.get_name(), file.get_link_target());
+        let size = file.get_size_for(state.size_mode);
+        let path = unwrapped_dir.get_file_path(file);
+        store.insert_with_values(parent, None, &columns, &[&icon, &name, &size, &get_percentage(size, dir_size),
+                                                            &get_percentage(size, state.root_size), &FILE_ID,
+                                                            &path.to_string_lossy().as_ref()]);
+    }
+}
+
+fn add_percentage_column(tree: &gtk::TreeView, id: i32, title: &str) {
+    let percentage_data_func: CellDataFunc = Box::new(move |_, render, model, iter| {
+        let cell = render.clone().downcast::<gtk::CellRendererText>().expect("Expected renderer to be CellRenderText");
+        let percentage = model.get_value(iter, id).get::<f64>()
+            .expect("Couldn't get percentage from tree model")
+            .expect("Couldn't get percentage from tree model");
+        cell.set_property_text(Some(&format!("{:.1}%", percentage)));
+    });
+    list_format::add_column(tree, id, title, Some(percentage_data_func), false, gtk::CellRendererText::new());
+}
+
+/// An alternative to the flat list in which directories expand in place. Rows are sorted by size within
+/// each level, and show their size relative to both their parent and the root of the scan.
+pub struct HierarchyView {
+    tree: gtk::TreeView,
+    store: gtk::TreeStore,
+    sort_store: gtk::TreeModelSort,
+    state: Rc<RefCell<HierarchyState>>
+}
+
+impl HierarchyView {
+    pub fn new() -> HierarchyView {
+        // icon, name, size, % of parent, % of root, directory id, full path of the entry
+        let store = gtk::TreeStore::new(&[String::static_type(), String::static_type(), u64::static_type(), f64::static_type(),
+                                          f64::static_type(), i64::static_type(), String::static_type()]);
+        let sort_store = gtk::TreeModelSort::new(&store);
+        sort_store.set_sort_column_id(gtk::SortColumn::Index(2), gtk::SortType::Descending);
+
+        let tree = gtk::TreeView::with_model(&sort_store);
+        list_format::add_icon_column(&tree, 0);
+        let name_column = list_format::add_column(&tree, 1, "Name", None, true, gtk::CellRendererText::new());
+        tree.set_expander_column(Some(&name_column));
+        add_percentage_column(&tree, 3, "% of parent");
+        add_percentage_column(&tree, 4, "% of total");
+        list_format::add_size_column(&tree, 2, "Size");
+
+        let state = Rc::new(RefCell::new(HierarchyState {
+            directories: Vec::new(),
+            root_size: 0,
+            size_mode: dir_walker::SizeMode::Apparent
+        }));
+
+        let expand_store = store.clone();
+        let expand_sort_store = sort_store.clone();
+        let expand_state = state.clone();
+        tree.connect_test_expand_row(move |_, iter, _| {
+            let child_iter = expand_sort_store.convert_iter_to_child_iter(iter);
+            let placeholder = expand_store.iter_children(Some(&child_iter))
+                                          .filter(|first| get_id(expand_store.upcast_ref(), first) == PLACEHOLDER_ID);
+            if let Some(placeholder) = placeholder {
+                let id = get_id(expand_store.upcast_ref(), &child_iter) as usize;
+                let mut state = expand_state.borrow_mut();
+                if let Some(dir) = state.directories[id].upgrade() {
+                    insert_children(&expand_store, Some(&child_iter), &dir, &mut state);
+                }
+                expand_store.remove(&placeholder);
+            }
+            Inhibit(false)
+        });
+
+        HierarchyView { tree, store, sort_store, state }
+    }
+
+    pub fn get_widget(&self) -> &gtk::TreeView {
+        &self.tree
+    }
+
+    /// Lists the contents of `dir` as the top level. Rows that were expanded or selected before, and are
+    /// still below `dir`, are expanded and selected again, so refreshing the same directory keeps the view as it was.
+    pub fn set_directory(&self, dir: &Mutex<dir_walker::Directory>, root_size: u64, size_mode: dir_walker::SizeMode) {
+        let mut expanded = Vec::new();
+        self.tree.map_expanded_rows(|_, path| {
+            if let Some(iter) = self.sort_store.get_iter(path) {
+                expanded.push(get_entry_path(self.sort_store.upcast_ref(), &iter));
+            }
+        });
+        let selected = self.tree.get_selection().get_selected()
+                           .map(|(model, iter)| get_entry_path(&model, &iter));
+
+        {
+            let mut state = self.state.borrow_mut();
+            state.directories.clear();
+            state.root_size = root_size;
+            state.size_mode = size_mode;
+            self.store.clear();
+            insert_children(&self.store, None, dir, &mut state);
+        }
+
+        // Parents come before their children, so each row has been loaded by the time it's looked for.
+        expanded.sort_by_key(|path| path.len());
+        for path in expanded {
+            if let Some(row) = self.find_row(None, Path::new(&path)) {
+                self.tree.expand_row(&row, false);
+            }
+        }
+        if let Some(row) = selected.and_then(|path| self.find_row(None, Path::new(&path))) {
+            self.tree.get_selection().select_path(&row);
+        }
+    }
+
+    /// The row of the entry at `path`, looking only through rows that have been loaded.
+    fn find_row(&self, parent: Option<&gtk::TreeIter>, path: &Path) -> Option<gtk::TreePath> {
+        let model: &gtk::TreeModel = self.sort_store.upcast_ref();
+        let iter = model.iter_children(parent)?;
+        loop {
+            let entry_path = get_entry_path(model, &iter);
+            if !entry_path.is_empty() && path.starts_with(&entry_path) {
+                if path == Path::new(&entry_path) {
+                    return model.get_path(&iter);
+                }
+                return self.find_row(Some(&iter), path);
+            }
+            if !model.iter_next(&iter) {
+                return None;
+            }
+        }
+    }
+
+    /// The directory in the row at `path`, which is a path in the view's sorted model.
+    pub fn get_directory(&self, path: &gtk::TreePath) -> Option<Arc<Mutex<dir_walker::Directory>>> {
+        let iter = self.sort_store.get_iter(path)?;
+        let id = get_id(self.sort_store.upcast_ref(), &iter);
+        if id < 0 {
+            return None;
+        }
+        self.state.borrow().directories[id as usize].upgrade()
+    }
+}
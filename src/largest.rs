@@ -0,0 +1,98 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+/// Keeps the `limit` largest items pushed into it, without holding on to the rest.
+pub struct Largest<T: Ord> {
+    limit: usize,
+    /// A min-heap, so the smallest item kept is the one that gets pushed out.
+    heap: BinaryHeap<Reverse<(u64, T)>>
+}
+
+impl<T: Ord> Largest<T> {
+    pub fn new(limit: usize) -> Largest<T> {
+        Largest { limit, heap: BinaryHeap::with_capacity(limit + 1) }
+    }
+
+    /// Whether an item of `size` would be kept. Lets callers skip building items that wouldn't be.
+    pub fn accepts(&self, size: u64) -> bool {
+        if self.heap.len() < self.limit {
+            return true;
+        }
+        match self.heap.peek() {
+            Some(Reverse((smallest, _))) => size > *smallest,
+            None => false
+        }
+    }
+
+    pub fn push(&mut self, size: u64, item: T) {
+        if !self.accepts(size) {
+            return;
+        }
+        self.heap.push(Reverse((size, item)));
+        if self.heap.len() > self.limit {
+            self.heap.pop();
+        }
+    }
+
+    /// The items kept, largest first.
+    pub fn into_sorted_vec(self) -> Vec<(u64, T)> {
+        // Sorting the reversed items ascending puts the largest first.
+        self.heap.into_sorted_vec().into_iter().map(|Reverse(item)| item).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_largest_items_largest_first() {
+        let mut largest = Largest::new(3);
+        for (size, name) in &[(5, "e"), (1, "a"), (9, "i"), (3, "c"), (7, "g")] {
+            largest.push(*size, *name);
+        }
+        assert_eq!(largest.into_sorted_vec(), vec![(9, "i"), (7, "g"), (5, "e")]);
+    }
+
+    #[test]
+    fn accepts_anything_until_full() {
+        let mut largest = Largest::new(2);
+        assert!(largest.accepts(0));
+        largest.push(10, "a");
+        assert!(largest.accepts(0));
+        largest.push(20, "b");
+        assert!(!largest.accepts(5));
+        assert!(!largest.accepts(10));
+        assert!(largest.accepts(11));
+    }
+
+    #[test]
+    fn ties_with_the_smallest_item_are_not_kept() {
+        let mut largest = Largest::new(2);
+        largest.push(10, "b");
+        largest.push(20, "c");
+        largest.push(10, "a");
+        assert_eq!(largest.into_sorted_vec(), vec![(20, "c"), (10, "b")]);
+    }
+
+    #[test]
+    fn items_of_equal_size_are_kept_until_full() {
+        let mut largest = Largest::new(3);
+        largest.push(10, "a");
+        largest.push(10, "c");
+        largest.push(10, "b");
+        assert_eq!(largest.into_sorted_vec(), vec![(10, "c"), (10, "b"), (10, "a")]);
+    }
+
+    #[test]
+    fn keeps_nothing_with_a_limit_of_zero() {
+        let mut largest = Largest::new(0);
+        assert!(!largest.accepts(u64::MAX));
+        largest.push(1, "a");
+        assert!(largest.into_sorted_vec().is_empty());
+    }
+}
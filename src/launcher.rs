@@ -0,0 +1,30 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use gio::prelude::*;
+use gtk::prelude::*;
+use std::path::Path;
+
+/// The launch context of the display `widget` is on, which lets the launched application show up on the
+/// right screen and with startup notification.
+fn get_launch_context(widget: &gtk::Widget) -> Option<gdk::AppLaunchContext> {
+    widget.get_display().get_app_launch_context()
+}
+
+/// Opens `path` with the application the user has chosen for its type. Directories open in the file manager.
+pub fn open(widget: &gtk::Widget, path: &Path) -> Result<(), glib::Error> {
+    let uri = gio::File::new_for_path(path).get_uri();
+    gio::AppInfo::launch_default_for_uri(&uri, get_launch_context(widget).as_ref())
+}
+
+/// Opens the user's shell in a terminal, starting in `dir`.
+pub fn open_terminal(widget: &gtk::Widget, dir: &Path) -> Result<(), glib::Error> {
+    let quoted_dir = glib::shell_quote(dir).unwrap_or_default();
+    let script = format!("cd {} && exec \"${{SHELL:-sh}}\"", quoted_dir.to_string_lossy());
+    let command_line = format!("sh -c {}", glib::shell_quote(&script).unwrap_or_default().to_string_lossy());
+    // The command line is parsed like the Exec key of a desktop file, where % starts a field code.
+    let app = gio::AppInfo::create_from_commandline(command_line.replace('%', "%%"), Some("Terminal"),
+                                                    gio::AppInfoCreateFlags::NEEDS_TERMINAL)?;
+    app.launch(&[], get_launch_context(widget).as_ref())
+}
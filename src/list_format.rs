@@ -0,0 +1,112 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use gtk::prelude::*;
+use humansize::{FileSize, file_size_opts as options};
+use super::dir_walker;
+
+pub static FOLDER_ICON: &str = "folder";
+static ERROR_ICON: &str = "dialog-error";
+static HARD_LINK_ICON: &str = "insert-link";
+static MOUNT_POINT_ICON: &str = "drive-harddisk";
+static SYMLINK_ICON: &str = "emblem-symbolic-link";
+
+/// Icons that are looked up by name rather than derived from a mime type.
+fn is_named_icon(icon_name: &str) -> bool {
+    icon_name == FOLDER_ICON || icon_name == ERROR_ICON || icon_name == HARD_LINK_ICON || icon_name == MOUNT_POINT_ICON
+        || icon_name == SYMLINK_ICON
+}
+
+pub fn get_directory_icon(dir: &dir_walker::Directory) -> &'static str {
+    if dir.has_error() {
+        ERROR_ICON
+    }
+    else if dir.is_mount_point() {
+        MOUNT_POINT_ICON
+    }
+    else if dir.get_link_target().is_some() {
+        SYMLINK_ICON
+    }
+    else {
+        FOLDER_ICON
+    }
+}
+
+/// A named icon for links, otherwise the file's mime type, which the icon column turns into an icon.
+pub fn get_file_icon(file: &dir_walker::File) -> &str {
+    if file.get_link_target().is_some() {
+        SYMLINK_ICON
+    }
+    else if file.is_hard_link() {
+        HARD_LINK_ICON
+    }
+    else {
+        file.get_mime()
+    }
+}
+
+/// Links are listed as "name → target" so it's obvious where their size comes from.
+pub fn get_display_name(name: &str, link_target: Option<&std::path::Path>) -> String {
+    match link_target {
+        Some(target) => format!("{} → {}", name, target.display()),
+        None => name.to_string()
+    }
+}
+
+pub type CellDataFunc = Box<dyn Fn(&gtk::TreeViewColumn, &gtk::CellRenderer, &gtk::TreeModel, &gtk::TreeIter) + 'static>;
+
+pub fn add_column<R: IsA<gtk::CellRenderer>>(tree: &gtk::TreeView, id: i32, title: &str, data_func: Option<CellDataFunc>,
+                                                is_sortable: bool, cell: R) -> gtk::TreeViewColumn
+{
+    let column = gtk::TreeViewColumn::new();
+
+    column.pack_start(&cell, true);
+    column.set_title(title);
+
+    if is_sortable {
+        column.set_clickable(true);
+        column.set_sort_indicator(true);
+        column.set_sort_column_id(id);
+    }
+
+    if data_func.is_some() {
+        gtk::TreeViewColumnExt::set_cell_data_func(&column, &cell, data_func);
+    }
+    else {
+        column.add_attribute(&cell, "text", id);
+    }
+    tree.append_column(&column);
+    column
+}
+
+/// Adds a column showing the icon named, or the mime type given, by column `id`.
+pub fn add_icon_column(tree: &gtk::TreeView, id: i32) {
+    let icon_data_func: CellDataFunc = Box::new(move |_, render, model, iter| {
+        let cell = render.clone().downcast::<gtk::CellRendererPixbuf>().expect("Expected renderer to be CellRenderText");
+        let model_val = model.get_value(iter, id);
+        let icon_name = model_val.get::<&str>().expect("Couldn't get icon name").expect("Couldn't get icon name");
+
+        if is_named_icon(icon_name) {
+            cell.set_property_icon_name(Some(icon_name));
+        }
+        else {
+            let icon = gio::content_type_get_icon(icon_name);
+            cell.set_property_gicon(icon.as_ref());
+        }
+    });
+    add_column(tree, id, "", Some(icon_data_func), false, gtk::CellRendererPixbuf::new());
+}
+
+/// Adds a sortable column showing the size in column `id` in human readable form.
+pub fn add_size_column(tree: &gtk::TreeView, id: i32, title: &str) {
+    let size_data_func: CellDataFunc = Box::new(move |_, render, model, iter| {
+        let cell = render.clone().downcast::<gtk::CellRendererText>().expect("Expected renderer to be CellRenderText");
+        let val = model.get_value(iter, id).get::<u64>()
+            .expect("Couldn't get size value from tree model")
+            .expect("Couldn't get size value from tree model");
+        let formatted_size = val.file_size(options::CONVENTIONAL).unwrap();
+        cell.set_property_text(Some(&formatted_size));
+    });
+    add_column(tree, id, title, Some(size_data_func), true, gtk::CellRendererText::new());
+}
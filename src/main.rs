@@ -6,8 +6,38 @@
 mod dir_walker;
 mod analyzer;
 mod config_window;
+mod error_list;
+mod cli;
+mod snapshot;
+mod dialogs;
+mod scan_diff;
+mod treemap;
+mod ring_chart;
+mod list_format;
+mod hierarchy_view;
+mod navigation;
+mod removal;
+mod launcher;
+mod watcher;
+mod duplicates;
+mod duplicate_list;
+mod largest;
+mod file_types;
+mod type_view;
+mod mime_sniff;
+mod top_entries;
+mod top_view;
 use relm::Widget;
 
 fn main() {
-   config_window::ConfigWindow::run(()).unwrap(); 
+    let args: Vec<std::ffi::OsString> = std::env::args_os().collect();
+    // "scan" as the first argument runs the command line scanner. Anything after "--" is a path to open,
+    // so a directory called scan can still be opened with "-- scan" (or "./scan").
+    let paths = match args.get(1).and_then(|arg| arg.to_str()) {
+        Some("scan") => std::process::exit(cli::run(&args[2..])),
+        Some("--") => &args[2..],
+        _ => &args[1..]
+    };
+    let paths = paths.iter().map(std::path::PathBuf::from).collect();
+    config_window::ConfigWindow::run(paths).unwrap(); 
 }
@@ -0,0 +1,146 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+/// How much of each file is read. Enough for the tar header, which is the furthest in.
+const SNIFF_LENGTH: u64 = 512;
+
+struct Signature {
+    offset: usize,
+    magic: &'static [u8],
+    mime: &'static str,
+    /// What the file has to start with as well, when `magic` isn't at the start.
+    prefix: &'static [u8],
+    /// Top-level types of the formats that are stored in this one, like application for the .docx and .jar
+    /// files that are zip files.
+    holds: &'static [&'static str]
+}
+
+const fn signature(offset: usize, magic: &'static [u8], mime: &'static str) -> Signature {
+    Signature { offset, magic, mime, prefix: b"", holds: &[] }
+}
+
+/// A format that comes in a RIFF file, which names it at offset 8.
+const fn riff(magic: &'static [u8], mime: &'static str) -> Signature {
+    Signature { offset: 8, magic, mime, prefix: b"RIFF", holds: &[] }
+}
+
+/// A format that other formats are stored in, whose own extension says more than the signature.
+const fn container(offset: usize, magic: &'static [u8], mime: &'static str, holds: &'static [&'static str]) -> Signature {
+    Signature { offset, magic, mime, prefix: b"", holds }
+}
+
+/// Checked in order, so more specific signatures come before ones they share a prefix with.
+static SIGNATURES: &[Signature] = &[
+    signature(0, b"\x89PNG\r\n\x1a\n", "image/png"),
+    signature(0, b"\xff\xd8\xff", "image/jpeg"),
+    signature(0, b"GIF87a", "image/gif"),
+    signature(0, b"GIF89a", "image/gif"),
+    riff(b"WEBP", "image/webp"),
+    riff(b"WAVE", "audio/x-wav"),
+    riff(b"AVI ", "video/x-msvideo"),
+    signature(0, b"II*\0", "image/tiff"),
+    signature(0, b"MM\0*", "image/tiff"),
+    // The brand after ftyp tells the formats of the ISO media file family apart.
+    signature(4, b"ftypheic", "image/heic"),
+    signature(4, b"ftypheix", "image/heic"),
+    signature(4, b"ftypavif", "image/avif"),
+    signature(4, b"ftypM4A ", "audio/mp4"),
+    signature(4, b"ftypqt  ", "video/quicktime"),
+    container(4, b"ftyp", "video/mp4", &["video", "audio", "image"]),
+    container(0, b"\x1a\x45\xdf\xa3", "video/x-matroska", &["video", "audio"]),
+    container(0, b"OggS", "audio/ogg", &["audio", "video"]),
+    signature(0, b"fLaC", "audio/flac"),
+    signature(0, b"ID3", "audio/mpeg"),
+    signature(0, b"\x1f\x8b", "application/gzip"),
+    signature(0, b"\x28\xb5\x2f\xfd", "application/zstd"),
+    signature(0, b"\xfd7zXZ\0", "application/x-xz"),
+    signature(0, b"BZh", "application/x-bzip2"),
+    container(0, b"PK\x03\x04", "application/zip", &["application"]),
+    container(0, b"PK\x05\x06", "application/zip", &["application"]),
+    signature(0, b"7z\xbc\xaf\x27\x1c", "application/x-7z-compressed"),
+    signature(0, b"Rar!\x1a\x07", "application/vnd.rar"),
+    signature(257, b"ustar", "application/x-tar"),
+    signature(0, b"%PDF-", "application/pdf"),
+    signature(0, b"SQLite format 3\0", "application/vnd.sqlite3"),
+    signature(0, b"\0asm", "application/wasm"),
+    signature(0, b"\xca\xfe\xba\xbe", "application/java-vm"),
+    signature(0, b"\xcf\xfa\xed\xfe", "application/x-mach-binary"),
+    signature(0, b"!<arch>\n", "application/x-archive"),
+    signature(0, b"\xed\xab\xee\xdb", "application/x-rpm"),
+    signature(0, b"LUKS\xba\xbe", "application/x-raw-disk-image")
+];
+
+/// ELF files are told apart by their type field: objects, executables, shared libraries and core dumps.
+fn get_elf_mime(header: &[u8]) -> Option<&'static str> {
+    if !header.starts_with(b"\x7fELF") || header.len() < 18 {
+        return None;
+    }
+    // Byte 5 says whether the fields that follow are little (1) or big (2) endian.
+    let elf_type = if header[5] == 2 {
+        u16::from_be_bytes([header[16], header[17]])
+    }
+    else {
+        u16::from_le_bytes([header[16], header[17]])
+    };
+    Some(match elf_type {
+        1 => "application/x-object",
+        3 => "application/x-sharedlib",
+        4 => "application/x-core",
+        _ => "application/x-executable"
+    })
+}
+
+/// Text files are valid UTF-8 without NUL bytes. The sample may end in the middle of a character.
+fn looks_like_text(header: &[u8]) -> bool {
+    if header.contains(&0) {
+        return false;
+    }
+    match std::str::from_utf8(header) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none()
+    }
+}
+
+/// What the start of a file says about its type.
+pub struct Sniffed {
+    /// The type of a recognised format, None when no signature matched.
+    pub mime: Option<&'static str>,
+    pub is_text: bool,
+    holds: &'static [&'static str]
+}
+
+impl Sniffed {
+    /// Whether a file of type `mime` could be stored in the recognised format, such as a .docx file in a zip
+    /// file. The type is then more specific than the signature.
+    pub fn may_hold(&self, mime: &str) -> bool {
+        matches!(mime.split('/').next(), Some(kind) if self.holds.contains(&kind))
+    }
+}
+
+/// Reads the start of the file at `path` and matches it against known signatures. None if the file
+/// couldn't be read.
+pub fn sniff(path: &Path) -> Option<Sniffed> {
+    let mut header = Vec::with_capacity(SNIFF_LENGTH as usize);
+    fs::File::open(path).ok()?.take(SNIFF_LENGTH).read_to_end(&mut header).ok()?;
+    if header.is_empty() {
+        return None;
+    }
+
+    let is_text = looks_like_text(&header);
+    if let Some(mime) = get_elf_mime(&header) {
+        return Some(Sniffed { mime: Some(mime), is_text, holds: &[] });
+    }
+    let signature = SIGNATURES.iter().find(|signature| {
+        header.starts_with(signature.prefix)
+            && matches!(header.get(signature.offset..), Some(rest) if rest.starts_with(signature.magic))
+    });
+    Some(match signature {
+        Some(signature) => Sniffed { mime: Some(signature.mime), is_text, holds: signature.holds },
+        None => Sniffed { mime: None, is_text, holds: &[] }
+    })
+}
@@ -0,0 +1,187 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use std::ffi::OsString;
+use std::sync::{Arc, Mutex};
+use super::dir_walker::Directory;
+
+/// A directory, stored as the names leading to it from the root of its tree rather than as a reference.
+/// That keeps it meaningful after the directory has been replaced by a rescan, and lets it be looked
+/// up in a different tree of the same root.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Location {
+    names: Vec<OsString>
+}
+
+impl Location {
+    pub fn of(dir: &Arc<Mutex<Directory>>) -> Location {
+        let mut names = Vec::new();
+        let mut current = dir.clone();
+        loop {
+            let (name, parent) = {
+                let unwrapped_dir = current.lock().unwrap();
+                (unwrapped_dir.get_os_name().to_os_string(), unwrapped_dir.get_parent().upgrade())
+            };
+            match parent {
+                Some(parent) => {
+                    names.push(name);
+                    current = parent;
+                },
+                None => break
+            }
+        }
+        names.reverse();
+        Location { names }
+    }
+
+    /// The directories from `root` down to this location, for as far as they exist in that tree.
+    pub fn get_ancestors(&self, root: &Arc<Mutex<Directory>>) -> Vec<Arc<Mutex<Directory>>> {
+        let mut ancestors = vec![root.clone()];
+        for name in self.names.iter() {
+            let next = ancestors.last().unwrap().lock().unwrap().get_subdirectories().iter()
+                                .find(|subdir| subdir.lock().unwrap().get_os_name() == name.as_os_str())
+                                .cloned();
+            match next {
+                Some(next) => ancestors.push(next),
+                None => break
+            }
+        }
+        ancestors
+    }
+
+    /// The directory at this location in `root`'s tree, if it's there.
+    pub fn find(&self, root: &Arc<Mutex<Directory>>) -> Option<Arc<Mutex<Directory>>> {
+        let mut ancestors = self.get_ancestors(root);
+        if ancestors.len() == self.names.len() + 1 {
+            ancestors.pop()
+        }
+        else {
+            None
+        }
+    }
+
+    /// The directory at this location in `root`'s tree or, if it's gone, its deepest ancestor that isn't.
+    pub fn resolve(&self, root: &Arc<Mutex<Directory>>) -> Arc<Mutex<Directory>> {
+        self.get_ancestors(root).pop().expect("Ancestors always include the root")
+    }
+}
+
+/// Back and forward stacks of the locations visited in the analyzer window.
+#[derive(Default)]
+pub struct History {
+    back: Vec<Location>,
+    forward: Vec<Location>
+}
+
+impl History {
+    /// Records that `from` was left by navigating somewhere new, which discards the forward stack.
+    pub fn push(&mut self, from: Location) {
+        if self.back.last() != Some(&from) {
+            self.back.push(from);
+        }
+        self.forward.clear();
+    }
+
+    pub fn go_back(&mut self, current: Location) -> Option<Location> {
+        let target = self.back.pop()?;
+        self.forward.push(current);
+        Some(target)
+    }
+
+    pub fn go_forward(&mut self, current: Location) -> Option<Location> {
+        let target = self.forward.pop()?;
+        self.back.push(current);
+        Some(target)
+    }
+
+    pub fn can_go_back(&self) -> bool {
+        !self.back.is_empty()
+    }
+
+    pub fn can_go_forward(&self) -> bool {
+        !self.forward.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use super::super::dir_walker::{self, ScanOptions, ScanProgress};
+
+    /// A location below the root given as a path relative to it.
+    fn location(path: &str) -> Location {
+        Location { names: Path::new(path).iter().map(|name| name.to_os_string()).collect() }
+    }
+
+    fn scan_temp_tree(name: &str, directories: &[&str]) -> (PathBuf, Arc<Mutex<Directory>>) {
+        let root = std::env::temp_dir().join(format!("disk_analyzer-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&root);
+        for directory in directories {
+            fs::create_dir_all(root.join(directory)).unwrap();
+        }
+        let (_cancel_sender, cancel_receiver) = std::sync::mpsc::channel();
+        let tree = dir_walker::read_dir(&root, cancel_receiver, &ScanOptions::default(), &ScanProgress::new());
+        fs::remove_dir_all(&root).unwrap();
+        (root, tree)
+    }
+
+    fn get_path(dir: &Arc<Mutex<Directory>>) -> PathBuf {
+        dir.lock().unwrap().get_path().to_path_buf()
+    }
+
+    #[test]
+    fn goes_back_and_forward_in_order() {
+        let mut history = History::default();
+        assert!(!history.can_go_back() && !history.can_go_forward());
+        history.push(location("a"));
+        history.push(location("a/b"));
+        assert_eq!(history.go_back(location("a/b/c")), Some(location("a/b")));
+        assert_eq!(history.go_back(location("a/b")), Some(location("a")));
+        assert!(!history.can_go_back());
+        assert_eq!(history.go_back(location("a")), None);
+        assert_eq!(history.go_forward(location("a")), Some(location("a/b")));
+        assert_eq!(history.go_forward(location("a/b")), Some(location("a/b/c")));
+        assert!(!history.can_go_forward());
+        assert_eq!(history.go_forward(location("a/b/c")), None);
+        assert_eq!(history.go_back(location("a/b/c")), Some(location("a/b")));
+    }
+
+    #[test]
+    fn navigating_somewhere_new_discards_the_forward_stack() {
+        let mut history = History::default();
+        history.push(location("a"));
+        assert_eq!(history.go_back(location("b")), Some(location("a")));
+        assert!(history.can_go_forward());
+        history.push(location("a"));
+        assert!(!history.can_go_forward());
+        assert_eq!(history.go_back(location("c")), Some(location("a")));
+        assert_eq!(history.go_forward(location("a")), Some(location("c")));
+    }
+
+    #[test]
+    fn leaving_the_same_location_twice_is_recorded_once() {
+        let mut history = History::default();
+        history.push(location("a"));
+        history.push(location("a"));
+        assert_eq!(history.go_back(location("b")), Some(location("a")));
+        assert!(!history.can_go_back());
+    }
+
+    #[test]
+    fn locations_are_found_in_another_scan_of_the_same_root() {
+        let (root, first) = scan_temp_tree("navigation-find", &["a/b/c", "d"]);
+        let (_, second) = scan_temp_tree("navigation-find", &["a/b", "d"]);
+        let c = Location::of(&location("a/b/c").find(&first).unwrap());
+        assert_eq!(c, location("a/b/c"));
+        assert_eq!(Location::of(&first), location(""));
+        assert!(c.find(&second).is_none());
+        assert_eq!(get_path(&c.resolve(&second)), root.join("a/b"));
+        assert_eq!(get_path(&location("d").find(&second).unwrap()), root.join("d"));
+        assert_eq!(get_path(&location("x/y").resolve(&second)), root);
+        let ancestors: Vec<PathBuf> = c.get_ancestors(&first).iter().map(get_path).collect();
+        assert_eq!(ancestors, vec![root.clone(), root.join("a"), root.join("a/b"), root.join("a/b/c")]);
+    }
+}
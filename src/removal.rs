@@ -0,0 +1,74 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use gio::prelude::*;
+use std::ffi::OsString;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use super::dir_walker;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    /// Moves entries to the trash as described by the freedesktop.org trash spec, so they can be restored.
+    Trash,
+    Delete
+}
+
+/// Where an entry sits in the scanned tree, so it can be taken out once it's gone from disk.
+pub enum Entry {
+    Directory(Arc<Mutex<dir_walker::Directory>>),
+    File(OsString)
+}
+
+pub struct Target {
+    pub parent: Arc<Mutex<dir_walker::Directory>>,
+    pub entry: Entry,
+    pub path: PathBuf,
+    /// The size shown to the user when asking for confirmation.
+    pub size: u64
+}
+
+/// The outcome of removing one target. The error is a message ready to be shown to the user.
+pub type Outcome = (Target, Result<(), String>);
+
+fn remove_path(path: &PathBuf, is_directory: bool, method: Method) -> Result<(), String> {
+    match method {
+        Method::Trash => gio::File::new_for_path(path).trash(None::<&gio::Cancellable>).map_err(|e| e.to_string()),
+        Method::Delete if is_directory => fs::remove_dir_all(path).map_err(|e| e.to_string()),
+        Method::Delete => fs::remove_file(path).map_err(|e| e.to_string())
+    }
+}
+
+/// Removes every target from disk. This blocks for as long as it takes to delete whole trees,
+/// so it should be run on its own thread.
+pub fn remove(targets: Vec<Target>, method: Method) -> Vec<Outcome> {
+    targets.into_iter().map(|target| {
+        // Symbolic links to directories are removed as links; their targets are left alone.
+        let is_directory = match &target.entry {
+            Entry::Directory(dir) => dir.lock().unwrap().get_link_target().is_none(),
+            Entry::File(_) => false
+        };
+        let result = remove_path(&target.path, is_directory, method);
+        (target, result)
+    }).collect()
+}
+
+/// Takes a target that was removed from disk out of the tree below `root`, subtracting its size from its ancestors.
+/// The tree may have been rescanned or patched since the target was chosen, so its parent is looked up again
+/// rather than trusted, and a target that's already gone is left alone.
+pub fn apply(root: &Arc<Mutex<dir_walker::Directory>>, target: &Target) {
+    let parent_path = target.parent.lock().unwrap().get_path().to_path_buf();
+    let parent = match dir_walker::find_directory(root, &parent_path) {
+        Some(parent) => parent,
+        None => return
+    };
+    match &target.entry {
+        Entry::Directory(dir) => {
+            let name = dir.lock().unwrap().get_os_name().to_os_string();
+            dir_walker::remove_subdirectory(&parent, &name);
+        },
+        Entry::File(name) => dir_walker::remove_file(&parent, name)
+    }
+}
@@ -0,0 +1,264 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use gtk::prelude::*;
+use humansize::{FileSize, file_size_opts as options};
+use std::cell::RefCell;
+use std::f64::consts::PI;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use super::dir_walker;
+
+/// How many rings are drawn around the current directory.
+const MAX_DEPTH: usize = 5;
+/// Arcs narrower than this (in radians) aren't drawn, and neither are their children.
+const MIN_ANGLE: f64 = 0.005;
+
+struct Segment {
+    depth: usize,
+    /// Angles are measured clockwise from 12 o'clock, in radians.
+    start: f64,
+    end: f64,
+    dir: Arc<Mutex<dir_walker::Directory>>,
+    path: String,
+    size: u64
+}
+
+#[derive(Default)]
+struct RingChartState {
+    directory: Option<Arc<Mutex<dir_walker::Directory>>>,
+    /// Shown in the centre when nothing is hovered.
+    directory_size: u64,
+    segments: Vec<Segment>,
+    hovered: Option<usize>
+}
+
+/// Where the centre of the chart is and how wide each ring is, for a widget of the given size.
+struct Geometry {
+    center_x: f64,
+    center_y: f64,
+    ring_width: f64
+}
+
+impl Geometry {
+    fn new(width: i32, height: i32) -> Geometry {
+        let radius = f64::from(width.min(height)) / 2.0 - 4.0;
+        Geometry {
+            center_x: f64::from(width) / 2.0,
+            center_y: f64::from(height) / 2.0,
+            // The centre circle takes up one and a half rings.
+            ring_width: (radius / (MAX_DEPTH as f64 + 1.5)).max(1.0)
+        }
+    }
+
+    fn get_inner_radius(&self, depth: usize) -> f64 {
+        self.ring_width * (depth as f64 + 1.5)
+    }
+}
+
+/// Which segment, if any, is at (x, y). Some(None) means the centre circle.
+fn get_segment_at(state: &RingChartState, geometry: &Geometry, x: f64, y: f64) -> Option<Option<usize>> {
+    let dx = x - geometry.center_x;
+    let dy = y - geometry.center_y;
+    let radius = (dx * dx + dy * dy).sqrt();
+    if radius < geometry.get_inner_radius(0) {
+        return Some(None);
+    }
+
+    let depth = ((radius / geometry.ring_width) - 1.5).floor() as usize;
+    let mut angle = dy.atan2(dx) + PI / 2.0;
+    if angle < 0.0 {
+        angle += 2.0 * PI;
+    }
+    state.segments.iter()
+         .position(|segment| segment.depth == depth && angle >= segment.start && angle < segment.end)
+         .map(Some)
+}
+
+fn layout_directory(dir: &Mutex<dir_walker::Directory>, start: f64, end: f64, depth: usize, size_mode: dir_walker::SizeMode,
+                    segments: &mut Vec<Segment>) {
+    let unwrapped_dir = dir.lock().unwrap();
+    let total = unwrapped_dir.get_size_for(size_mode);
+    if total == 0 {
+        return;
+    }
+
+    // Files get no arc of their own, so a directory's children only fill the share of it they take up.
+    let scale = (end - start) / total as f64;
+    let mut subdirs: Vec<(u64, &Arc<Mutex<dir_walker::Directory>>)> = unwrapped_dir.get_subdirectories().iter()
+        .map(|subdir| (subdir.lock().unwrap().get_size_for(size_mode), subdir))
+        .collect();
+    subdirs.sort_by_key(|(size, _)| std::cmp::Reverse(*size));
+
+    let mut angle = start;
+    for (size, subdir) in subdirs {
+        let sweep = size as f64 * scale;
+        if sweep < MIN_ANGLE {
+            break;
+        }
+        segments.push(Segment {
+            depth,
+            start: angle,
+            end: angle + sweep,
+            dir: subdir.clone(),
+            path: subdir.lock().unwrap().get_display_path().into_owned(),
+            size
+        });
+        if depth + 1 < MAX_DEPTH {
+            layout_directory(subdir, angle, angle + sweep, depth + 1, size_mode, segments);
+        }
+        angle += sweep;
+    }
+}
+
+fn hsv_to_rgb(hue: f64, saturation: f64, value: f64) -> (f64, f64, f64) {
+    let sector = (hue * 6.0).floor();
+    let fraction = hue * 6.0 - sector;
+    let p = value * (1.0 - saturation);
+    let q = value * (1.0 - fraction * saturation);
+    let t = value * (1.0 - (1.0 - fraction) * saturation);
+    match sector as i32 % 6 {
+        0 => (value, t, p),
+        1 => (q, value, p),
+        2 => (p, value, t),
+        3 => (p, q, value),
+        4 => (t, p, value),
+        _ => (value, p, q)
+    }
+}
+
+fn draw_centered_text(cr: &cairo::Context, text: &str, x: f64, y: f64) {
+    let extents = cr.text_extents(text);
+    cr.move_to(x - extents.width / 2.0 - extents.x_bearing, y);
+    cr.show_text(text);
+}
+
+fn draw(state: &RingChartState, geometry: &Geometry, cr: &cairo::Context) {
+    cr.set_line_width(1.0);
+    for (index, segment) in state.segments.iter().enumerate() {
+        // Each arc takes its hue from where it sits around the circle, and gets paler further out.
+        let hue = (segment.start + segment.end) / 2.0 / (2.0 * PI);
+        let saturation = 0.75 - 0.1 * segment.depth as f64;
+        let value = if state.hovered == Some(index) { 1.0 } else { 0.85 };
+        let (red, green, blue) = hsv_to_rgb(hue, saturation, value);
+
+        let inner = geometry.get_inner_radius(segment.depth);
+        let outer = inner + geometry.ring_width;
+        let start = segment.start - PI / 2.0;
+        let end = segment.end - PI / 2.0;
+        cr.new_path();
+        cr.arc(geometry.center_x, geometry.center_y, outer, start, end);
+        cr.arc_negative(geometry.center_x, geometry.center_y, inner, end, start);
+        cr.close_path();
+        cr.set_source_rgb(red, green, blue);
+        cr.fill_preserve();
+        cr.set_source_rgb(1.0, 1.0, 1.0);
+        cr.stroke();
+    }
+
+    cr.new_path();
+    cr.arc(geometry.center_x, geometry.center_y, geometry.get_inner_radius(0), 0.0, 2.0 * PI);
+    cr.set_source_rgb(0.9, 0.9, 0.9);
+    cr.fill();
+
+    let (name, size) = match state.hovered.map(|index| &state.segments[index]) {
+        Some(segment) => (segment.dir.lock().unwrap().get_name().into_owned(), segment.size),
+        None => match &state.directory {
+            Some(dir) => (dir.lock().unwrap().get_name().into_owned(), state.directory_size),
+            None => return
+        }
+    };
+    cr.select_font_face("Sans", cairo::FontSlant::Normal, cairo::FontWeight::Bold);
+    cr.set_font_size(11.0);
+    cr.set_source_rgb(0.1, 0.1, 0.1);
+    draw_centered_text(cr, &name, geometry.center_x, geometry.center_y - 2.0);
+    cr.select_font_face("Sans", cairo::FontSlant::Normal, cairo::FontWeight::Normal);
+    draw_centered_text(cr, &size.file_size(options::CONVENTIONAL).unwrap(), geometry.center_x, geometry.center_y + 12.0);
+}
+
+/// A sunburst chart of the subdirectories of a directory, a few levels deep. Like `Treemap`, clicks
+/// are left to the owner, which looks up what was clicked with `get_directory_at`.
+pub struct RingChart {
+    drawing_area: gtk::DrawingArea,
+    state: Rc<RefCell<RingChartState>>
+}
+
+impl RingChart {
+    pub fn new() -> RingChart {
+        let drawing_area = gtk::DrawingArea::new();
+        drawing_area.set_size_request(200, 200);
+        drawing_area.set_has_tooltip(true);
+        drawing_area.add_events(gdk::EventMask::POINTER_MOTION_MASK | gdk::EventMask::BUTTON_PRESS_MASK
+                                | gdk::EventMask::LEAVE_NOTIFY_MASK);
+        let state = Rc::new(RefCell::new(RingChartState::default()));
+
+        let draw_state = state.clone();
+        drawing_area.connect_draw(move |area, cr| {
+            let geometry = Geometry::new(area.get_allocated_width(), area.get_allocated_height());
+            draw(&draw_state.borrow(), &geometry, cr);
+            Inhibit(false)
+        });
+
+        let motion_state = state.clone();
+        drawing_area.connect_motion_notify_event(move |area, event| {
+            let (x, y) = event.get_position();
+            let geometry = Geometry::new(area.get_allocated_width(), area.get_allocated_height());
+            let mut state = motion_state.borrow_mut();
+            let hovered = get_segment_at(&state, &geometry, x, y).flatten();
+            if hovered != state.hovered {
+                state.hovered = hovered;
+                area.queue_draw();
+            }
+            Inhibit(false)
+        });
+
+        let leave_state = state.clone();
+        drawing_area.connect_leave_notify_event(move |area, _| {
+            leave_state.borrow_mut().hovered = None;
+            area.queue_draw();
+            Inhibit(false)
+        });
+
+        let tooltip_state = state.clone();
+        drawing_area.connect_query_tooltip(move |area, x, y, _, tooltip| {
+            let geometry = Geometry::new(area.get_allocated_width(), area.get_allocated_height());
+            let state = tooltip_state.borrow();
+            match get_segment_at(&state, &geometry, f64::from(x), f64::from(y)) {
+                Some(Some(index)) => {
+                    let segment = &state.segments[index];
+                    tooltip.set_text(Some(&format!("{}\n{}", segment.path, segment.size.file_size(options::CONVENTIONAL).unwrap())));
+                    true
+                },
+                _ => false
+            }
+        });
+
+        RingChart { drawing_area, state }
+    }
+
+    pub fn get_widget(&self) -> &gtk::DrawingArea {
+        &self.drawing_area
+    }
+
+    /// Shows `dir`. Also called when the size mode changes, since that changes every arc.
+    pub fn set_directory(&self, dir: &Arc<Mutex<dir_walker::Directory>>, size_mode: dir_walker::SizeMode) {
+        let mut state = self.state.borrow_mut();
+        state.segments.clear();
+        state.hovered = None;
+        layout_directory(dir, 0.0, 2.0 * PI, 0, size_mode, &mut state.segments);
+        state.directory_size = dir.lock().unwrap().get_size_for(size_mode);
+        state.directory = Some(dir.clone());
+        self.drawing_area.queue_draw();
+    }
+
+    /// The directory drawn at (x, y). Clicking the centre circle goes up to the parent directory.
+    pub fn get_directory_at(&self, x: f64, y: f64) -> Option<Arc<Mutex<dir_walker::Directory>>> {
+        let geometry = Geometry::new(self.drawing_area.get_allocated_width(), self.drawing_area.get_allocated_height());
+        let state = self.state.borrow();
+        match get_segment_at(&state, &geometry, x, y)? {
+            Some(index) => Some(state.segments[index].dir.clone()),
+            None => state.directory.as_ref().and_then(|dir| dir.lock().unwrap().get_parent().upgrade())
+        }
+    }
+}
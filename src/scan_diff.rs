@@ -0,0 +1,264 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use super::dir_walker::{Directory, SizeMode};
+
+/// How an entry changed between an earlier scan and the one being viewed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Change {
+    Added,
+    Removed,
+    Grown,
+    Shrunk,
+    Unchanged
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = match self {
+            Change::Added => "Added",
+            Change::Removed => "Removed",
+            Change::Grown => "Grown",
+            Change::Shrunk => "Shrunk",
+            Change::Unchanged => "Unchanged"
+        };
+        write!(f, "{}", description)
+    }
+}
+
+/// The sizes of one entry in both scans. A size is 0 in the scan the entry is missing from.
+#[derive(Clone, Copy, Debug)]
+pub struct EntryChange {
+    pub change: Change,
+    pub old_size: u64,
+    pub new_size: u64
+}
+
+impl EntryChange {
+    fn new(old_size: Option<u64>, new_size: Option<u64>) -> EntryChange {
+        let change = match (old_size, new_size) {
+            (None, _) => Change::Added,
+            (_, None) => Change::Removed,
+            (Some(old), Some(new)) if new > old => Change::Grown,
+            (Some(old), Some(new)) if new < old => Change::Shrunk,
+            _ => Change::Unchanged
+        };
+        EntryChange {
+            change,
+            old_size: old_size.unwrap_or(0),
+            new_size: new_size.unwrap_or(0)
+        }
+    }
+
+    pub fn get_delta(&self) -> i64 {
+        self.new_size as i64 - self.old_size as i64
+    }
+
+    /// The delta relative to the old size. None when there was no old size to compare against.
+    pub fn get_delta_percent(&self) -> Option<f64> {
+        if self.old_size == 0 {
+            None
+        }
+        else {
+            Some(self.get_delta() as f64 / self.old_size as f64 * 100.0)
+        }
+    }
+}
+
+/// An entry that only exists in the earlier scan.
+pub struct RemovedEntry {
+    pub name: OsString,
+    /// None for directories.
+    pub mime: Option<String>,
+    pub size: u64
+}
+
+/// One directory of the current scan compared with the same directory in the earlier one.
+/// `subdirectories` and `files` are in the same order as the directory's own lists.
+pub struct DirectoryComparison {
+    pub subdirectories: Vec<EntryChange>,
+    pub files: Vec<EntryChange>,
+    pub removed: Vec<RemovedEntry>
+}
+
+/// Compares the entries of `dir` with those of `baseline`, which is None when the whole directory is new.
+pub fn compare_directory(baseline: Option<&Directory>, dir: &Directory, size_mode: SizeMode) -> DirectoryComparison {
+    let mut old_subdirectories: HashMap<OsString, u64> = HashMap::new();
+    let mut old_files: HashMap<&OsStr, (u64, &str)> = HashMap::new();
+    if let Some(baseline) = baseline {
+        for subdir in baseline.get_subdirectories() {
+            let unwrapped_subdir = subdir.lock().unwrap();
+            old_subdirectories.insert(unwrapped_subdir.get_os_name().to_os_string(), unwrapped_subdir.get_size_for(size_mode));
+        }
+        for file in baseline.get_files() {
+            old_files.insert(file.get_os_name(), (file.get_size_for(size_mode), file.get_mime()));
+        }
+    }
+
+    let subdirectories = dir.get_subdirectories().iter().map(|subdir| {
+        let unwrapped_subdir = subdir.lock().unwrap();
+        let old_size = old_subdirectories.remove(unwrapped_subdir.get_os_name());
+        EntryChange::new(old_size, Some(unwrapped_subdir.get_size_for(size_mode)))
+    }).collect();
+
+    let files = dir.get_files().iter().map(|file| {
+        let old_size = old_files.remove(file.get_os_name()).map(|(size, _)| size);
+        EntryChange::new(old_size, Some(file.get_size_for(size_mode)))
+    }).collect();
+
+    let mut removed: Vec<RemovedEntry> = old_subdirectories.into_iter()
+        .map(|(name, size)| RemovedEntry { name, mime: None, size })
+        .collect();
+    removed.extend(old_files.into_iter().map(|(name, (size, mime))| RemovedEntry {
+        name: name.to_os_string(),
+        mime: Some(mime.to_string()),
+        size
+    }));
+
+    DirectoryComparison { subdirectories, files, removed }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::sync::{Arc, Mutex};
+    use super::super::dir_walker::{self, ScanOptions, ScanProgress};
+
+    /// Replaces the directory called `name` with one holding `files`, given as paths below it and their sizes.
+    fn create_temp_tree(name: &str, files: &[(&str, usize)]) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("disk_analyzer-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        for (path, size) in files {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, vec![0u8; *size]).unwrap();
+        }
+        root
+    }
+
+    fn scan(path: &Path) -> Arc<Mutex<Directory>> {
+        let (_cancel_sender, cancel_receiver) = std::sync::mpsc::channel();
+        dir_walker::read_dir(&path.to_path_buf(), cancel_receiver, &ScanOptions::default(), &ScanProgress::new())
+    }
+
+    /// Scans the tree before and after running `change` on it and compares the two roots.
+    fn compare_scans(name: &str, files: &[(&str, usize)], change: impl FnOnce(&Path)) -> (Directory, DirectoryComparison) {
+        let root = create_temp_tree(name, files);
+        let baseline = scan(&root);
+        change(&root);
+        let current = scan(&root);
+        fs::remove_dir_all(&root).unwrap();
+        let baseline = baseline.lock().unwrap();
+        let current = current.lock().unwrap().clone();
+        let comparison = compare_directory(Some(&baseline), &current, SizeMode::Apparent);
+        (current, comparison)
+    }
+
+    fn get_file_changes(dir: &Directory, comparison: &DirectoryComparison) -> Vec<(String, Change, u64, u64)> {
+        let mut changes: Vec<(String, Change, u64, u64)> = dir.get_files().iter().zip(&comparison.files)
+            .map(|(file, entry)| (file.get_name().into_owned(), entry.change, entry.old_size, entry.new_size))
+            .collect();
+        changes.sort_by(|a, b| a.0.cmp(&b.0));
+        changes
+    }
+
+    fn get_subdirectory_changes(dir: &Directory, comparison: &DirectoryComparison) -> Vec<(String, Change)> {
+        let mut changes: Vec<(String, Change)> = dir.get_subdirectories().iter().zip(&comparison.subdirectories)
+            .map(|(subdir, entry)| (subdir.lock().unwrap().get_name().into_owned(), entry.change))
+            .collect();
+        changes.sort_by(|a, b| a.0.cmp(&b.0));
+        changes
+    }
+
+    fn get_removed(comparison: &DirectoryComparison) -> Vec<(String, Option<String>, u64)> {
+        let mut removed: Vec<(String, Option<String>, u64)> = comparison.removed.iter()
+            .map(|entry| (entry.name.to_string_lossy().into_owned(), entry.mime.clone(), entry.size))
+            .collect();
+        removed.sort();
+        removed
+    }
+
+    #[test]
+    fn files_are_compared_by_name() {
+        let (current, comparison) = compare_scans("diff-files",
+            &[("same", 100), ("grown", 100), ("shrunk", 100), ("removed.txt", 50)],
+            |root| {
+                fs::write(root.join("grown"), vec![0u8; 250]).unwrap();
+                fs::write(root.join("shrunk"), vec![0u8; 10]).unwrap();
+                fs::remove_file(root.join("removed.txt")).unwrap();
+                fs::write(root.join("added"), vec![0u8; 5]).unwrap();
+            });
+        assert_eq!(get_file_changes(&current, &comparison), vec![
+            ("added".to_string(), Change::Added, 0, 5),
+            ("grown".to_string(), Change::Grown, 100, 250),
+            ("same".to_string(), Change::Unchanged, 100, 100),
+            ("shrunk".to_string(), Change::Shrunk, 100, 10)
+        ]);
+        assert_eq!(get_removed(&comparison), vec![("removed.txt".to_string(), Some("text/plain".to_string()), 50)]);
+        assert!(comparison.subdirectories.is_empty());
+    }
+
+    #[test]
+    fn subdirectories_are_compared_by_total_size() {
+        let (current, comparison) = compare_scans("diff-directories",
+            &[("same/file", 10), ("grown/file", 10), ("shrunk/file", 1000), ("removed/file", 10)],
+            |root| {
+                fs::write(root.join("grown/other"), vec![0u8; 10]).unwrap();
+                fs::write(root.join("shrunk/file"), vec![0u8; 10]).unwrap();
+                fs::remove_dir_all(root.join("removed")).unwrap();
+                fs::create_dir(root.join("added")).unwrap();
+            });
+        assert_eq!(get_subdirectory_changes(&current, &comparison), vec![
+            ("added".to_string(), Change::Added),
+            ("grown".to_string(), Change::Grown),
+            ("same".to_string(), Change::Unchanged),
+            ("shrunk".to_string(), Change::Shrunk)
+        ]);
+        let removed = get_removed(&comparison);
+        assert_eq!(removed.len(), 1);
+        assert_eq!((removed[0].0.as_str(), &removed[0].1), ("removed", &None));
+        assert!(removed[0].2 >= 10);
+    }
+
+    #[test]
+    fn a_file_replaced_by_a_directory_is_removed_and_added() {
+        let (current, comparison) = compare_scans("diff-replaced", &[("entry", 10)], |root| {
+            fs::remove_file(root.join("entry")).unwrap();
+            fs::create_dir(root.join("entry")).unwrap();
+        });
+        assert_eq!(get_subdirectory_changes(&current, &comparison), vec![("entry".to_string(), Change::Added)]);
+        assert_eq!(get_removed(&comparison).len(), 1);
+        assert_eq!(get_removed(&comparison)[0].2, 10);
+    }
+
+    #[test]
+    fn everything_is_added_without_a_baseline() {
+        let root = create_temp_tree("diff-new", &[("file", 10), ("directory/file", 20)]);
+        let current = scan(&root);
+        fs::remove_dir_all(&root).unwrap();
+        let current = current.lock().unwrap();
+        let comparison = compare_directory(None, &current, SizeMode::Apparent);
+        assert_eq!(get_file_changes(&current, &comparison), vec![("file".to_string(), Change::Added, 0, 10)]);
+        assert_eq!(get_subdirectory_changes(&current, &comparison), vec![("directory".to_string(), Change::Added)]);
+        assert!(comparison.removed.is_empty());
+    }
+
+    #[test]
+    fn deltas_are_relative_to_the_old_size() {
+        let grown = EntryChange::new(Some(200), Some(300));
+        assert_eq!(grown.get_delta(), 100);
+        assert_eq!(grown.get_delta_percent(), Some(50.0));
+        let removed = EntryChange::new(Some(200), None);
+        assert_eq!((removed.change, removed.get_delta()), (Change::Removed, -200));
+        assert_eq!(removed.get_delta_percent(), Some(-100.0));
+        assert_eq!(EntryChange::new(None, Some(10)).get_delta_percent(), None);
+        assert_eq!(EntryChange::new(Some(0), Some(0)).change, Change::Unchanged);
+    }
+}
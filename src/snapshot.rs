@@ -0,0 +1,1195 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use flate2::Compression;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+use super::dir_walker::{Directory, ErrorCategory, File, Operation, ReadError, MimeDetection, ScanInfo, ScanOptions, SymlinkPolicy};
+
+static JSON_FORMAT: &str = "disk_analyzer-scan";
+/// Bump whenever a change to the layout would stop older readers from understanding a file.
+const JSON_VERSION: u32 = 1;
+
+static BINARY_MAGIC: &[u8; 8] = b"DSKSCAN\0";
+/// Same rule as `JSON_VERSION`.
+const BINARY_VERSION: u32 = 4;
+/// The oldest binary version that can still be read. Version 1 didn't record how mime types were detected,
+/// and version 3 wrote file and directory names out in full instead of interning them.
+const OLDEST_BINARY_VERSION: u32 = 1;
+/// The extension offered when saving a scan. Anything not ending in .json is saved in the binary format.
+pub static BINARY_EXTENSION: &str = "diskscan";
+
+const DIRECTORY_HAS_PATH: u64 = 1;
+const DIRECTORY_MOUNT_POINT: u64 = 1 << 1;
+const DIRECTORY_HAS_MODIFIED: u64 = 1 << 2;
+const DIRECTORY_HAS_ERROR: u64 = 1 << 3;
+const DIRECTORY_HAS_LINK_TARGET: u64 = 1 << 4;
+
+const FILE_HARD_LINK: u64 = 1;
+const FILE_HAS_MODIFIED: u64 = 1 << 1;
+const FILE_HAS_LINK_TARGET: u64 = 1 << 2;
+
+#[derive(Error, Debug)]
+pub enum SnapshotError {
+    #[error("Could not access the file: {0}")]
+    IOError(#[from] std::io::Error),
+    #[error("The file is not a valid scan: {0}")]
+    JsonError(#[from] serde_json::Error),
+    #[error("The file is not a saved disk analyzer scan")]
+    NotASnapshot,
+    #[error("The scan was saved in format version {found}, but only {} can be read", describe_versions(*.oldest, *.newest))]
+    UnsupportedVersion { found: u32, oldest: u32, newest: u32 },
+    #[error("The file is damaged or incomplete")]
+    Corrupt
+}
+
+fn describe_versions(oldest: u32, newest: u32) -> String {
+    if oldest == newest {
+        format!("version {}", newest)
+    }
+    else {
+        format!("versions {} to {}", oldest, newest)
+    }
+}
+
+/// A file name or path. Names that aren't valid UTF-8 are kept as raw bytes so they survive the round trip.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum JsonName {
+    Text(String),
+    Bytes(Vec<u8>)
+}
+
+impl JsonName {
+    #[cfg(unix)]
+    fn new(name: &OsStr) -> JsonName {
+        use std::os::unix::ffi::OsStrExt;
+        match name.to_str() {
+            Some(text) => JsonName::Text(text.to_string()),
+            None => JsonName::Bytes(name.as_bytes().to_vec())
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn new(name: &OsStr) -> JsonName {
+        JsonName::Text(name.to_string_lossy().into_owned())
+    }
+
+    #[cfg(unix)]
+    fn into_os_string(self) -> OsString {
+        use std::os::unix::ffi::OsStringExt;
+        match self {
+            JsonName::Text(text) => OsString::from(text),
+            JsonName::Bytes(bytes) => OsString::from_vec(bytes)
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn into_os_string(self) -> OsString {
+        match self {
+            JsonName::Text(text) => OsString::from(text),
+            JsonName::Bytes(bytes) => OsString::from(String::from_utf8_lossy(&bytes).into_owned())
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct JsonError {
+    path: JsonName,
+    operation: String,
+    category: String,
+    message: String
+}
+
+#[derive(Serialize, Deserialize)]
+struct JsonFile {
+    name: JsonName,
+    size: u64,
+    allocated_size: u64,
+    mime: String,
+    #[serde(default, skip_serializing_if = "is_false")]
+    hard_link: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    link_target: Option<JsonName>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    modified: Option<u64>
+}
+
+#[derive(Serialize, Deserialize)]
+struct JsonDirectory {
+    name: JsonName,
+    /// Only stored when it can't be worked out from the parent's path and the name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    path: Option<JsonName>,
+    size: u64,
+    allocated_size: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    modified: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<JsonError>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    entry_errors: Vec<JsonError>,
+    #[serde(default, skip_serializing_if = "is_false")]
+    mount_point: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    link_target: Option<JsonName>,
+    directories: Vec<JsonDirectory>,
+    files: Vec<JsonFile>
+}
+
+#[derive(Serialize, Deserialize)]
+struct JsonScanOptions {
+    threads: usize,
+    one_filesystem: bool,
+    symlinks: String,
+    /// Missing from scans saved before it could be chosen, which went by extension.
+    #[serde(default)]
+    mime_detection: String
+}
+
+#[derive(Serialize, Deserialize)]
+struct JsonSnapshot {
+    format: String,
+    version: u32,
+    scanned_at: Option<u64>,
+    paths: Vec<JsonName>,
+    options: JsonScanOptions,
+    root: JsonDirectory
+}
+
+/// Read first, so that files from a newer version are rejected before their tree is parsed.
+#[derive(Deserialize)]
+struct JsonHeader {
+    format: Option<String>,
+    version: Option<u32>
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+fn to_timestamp(time: SystemTime) -> Option<u64> {
+    time.duration_since(UNIX_EPOCH).ok().map(|duration| duration.as_secs())
+}
+
+fn from_timestamp(seconds: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(seconds)
+}
+
+fn operation_to_str(operation: Operation) -> &'static str {
+    match operation {
+        Operation::OpenDirectory => "open_directory",
+        Operation::ListDirectory => "list_directory",
+        Operation::Metadata => "metadata",
+        Operation::ReadLink => "read_link"
+    }
+}
+
+fn operation_from_str(operation: &str) -> Operation {
+    match operation {
+        "open_directory" => Operation::OpenDirectory,
+        "list_directory" => Operation::ListDirectory,
+        "read_link" => Operation::ReadLink,
+        _ => Operation::Metadata
+    }
+}
+
+fn category_to_str(category: ErrorCategory) -> &'static str {
+    match category {
+        ErrorCategory::PermissionDenied => "permission_denied",
+        ErrorCategory::NotFound => "not_found",
+        ErrorCategory::Other => "other"
+    }
+}
+
+/// Only the category of an error is saved, so it comes back as the most typical kind for it.
+fn category_to_kind(category: &str) -> std::io::ErrorKind {
+    match category {
+        "permission_denied" => std::io::ErrorKind::PermissionDenied,
+        "not_found" => std::io::ErrorKind::NotFound,
+        _ => std::io::ErrorKind::Other
+    }
+}
+
+fn symlinks_to_str(symlinks: SymlinkPolicy) -> &'static str {
+    match symlinks {
+        SymlinkPolicy::Ignore => "ignore",
+        SymlinkPolicy::List => "list",
+        SymlinkPolicy::Follow => "follow"
+    }
+}
+
+fn symlinks_from_str(symlinks: &str) -> SymlinkPolicy {
+    match symlinks {
+        "ignore" => SymlinkPolicy::Ignore,
+        "follow" => SymlinkPolicy::Follow,
+        _ => SymlinkPolicy::List
+    }
+}
+
+fn mime_detection_to_str(mime_detection: MimeDetection) -> &'static str {
+    match mime_detection {
+        MimeDetection::Extension => "extension",
+        MimeDetection::SniffUnknown => "sniff_unknown",
+        MimeDetection::SniffAll => "sniff_all"
+    }
+}
+
+fn mime_detection_from_str(mime_detection: &str) -> MimeDetection {
+    match mime_detection {
+        "sniff_unknown" => MimeDetection::SniffUnknown,
+        "sniff_all" => MimeDetection::SniffAll,
+        _ => MimeDetection::Extension
+    }
+}
+
+fn export_error(error: &ReadError) -> Option<JsonError> {
+    match error {
+        ReadError::IOError { path, operation, message, .. } => Some(JsonError {
+            path: JsonName::new(path.as_os_str()),
+            operation: operation_to_str(*operation).to_string(),
+            category: category_to_str(error.get_category()).to_string(),
+            message: message.clone()
+        }),
+        ReadError::OperationCancelled => None
+    }
+}
+
+fn import_error(error: JsonError) -> ReadError {
+    ReadError::IOError {
+        path: PathBuf::from(error.path.into_os_string()),
+        operation: operation_from_str(&error.operation),
+        kind: category_to_kind(&error.category),
+        message: error.message
+    }
+}
+
+fn export_file(file: &File) -> JsonFile {
+    JsonFile {
+        name: JsonName::new(file.get_os_name()),
+        size: file.get_size(),
+        allocated_size: file.get_allocated_size(),
+        mime: file.get_mime().to_string(),
+        hard_link: file.is_hard_link(),
+        link_target: file.get_link_target().map(|target| JsonName::new(target.as_os_str())),
+        modified: file.get_modified().and_then(to_timestamp)
+    }
+}
+
+fn import_file(json: JsonFile) -> File {
+    let mut file = File::new(&json.name.into_os_string(), json.size, json.allocated_size, &json.mime, json.hard_link);
+    file.set_link_target(json.link_target.map(|target| PathBuf::from(target.into_os_string())));
+    file.set_modified(json.modified.map(from_timestamp));
+    file
+}
+
+fn export_directory(dir: &Directory, parent_path: Option<&Path>) -> JsonDirectory {
+    let derived_path = parent_path.map(|parent_path| parent_path.join(dir.get_os_name()));
+    let path = if derived_path.as_deref() == Some(dir.get_path()) {
+        None
+    }
+    else {
+        Some(JsonName::new(dir.get_path().as_os_str()))
+    };
+
+    JsonDirectory {
+        name: JsonName::new(dir.get_os_name()),
+        path,
+        size: dir.get_size(),
+        allocated_size: dir.get_allocated_size(),
+        modified: dir.get_modified().and_then(to_timestamp),
+        error: dir.get_error().as_ref().and_then(export_error),
+        entry_errors: dir.get_entry_errors().iter().filter_map(export_error).collect(),
+        mount_point: dir.is_mount_point(),
+        link_target: dir.get_link_target().map(|target| JsonName::new(target.as_os_str())),
+        directories: dir.get_subdirectories().iter()
+                        .map(|subdir| export_directory(&subdir.lock().unwrap(), Some(dir.get_path())))
+                        .collect(),
+        files: dir.get_files().iter().map(export_file).collect()
+    }
+}
+
+fn import_directory(json: JsonDirectory, parent: Weak<Mutex<Directory>>, parent_path: &Path) -> Arc<Mutex<Directory>> {
+    let name = json.name.into_os_string();
+    let path = match json.path {
+        Some(path) => PathBuf::from(path.into_os_string()),
+        None => parent_path.join(&name)
+    };
+
+    let directory = Arc::new(Mutex::new(Directory::new(&name, parent, &path)));
+    let subdirectories = json.directories.into_iter()
+                             .map(|subdir| import_directory(subdir, Arc::downgrade(&directory), &path))
+                             .collect();
+
+    if let Ok(mut unwrapped_dir) = directory.lock() {
+        unwrapped_dir.set_size(json.size);
+        unwrapped_dir.set_allocated_size(json.allocated_size);
+        unwrapped_dir.set_modified(json.modified.map(from_timestamp));
+        unwrapped_dir.set_error(json.error.map(import_error));
+        unwrapped_dir.set_entry_errors(json.entry_errors.into_iter().map(import_error).collect());
+        unwrapped_dir.set_mount_point(json.mount_point);
+        unwrapped_dir.set_link_target(json.link_target.map(|target| PathBuf::from(target.into_os_string())));
+        unwrapped_dir.set_subdirectories(subdirectories);
+        unwrapped_dir.set_files(json.files.into_iter().map(import_file).collect());
+    }
+    directory
+}
+
+/// Writes a finished scan to `path` as JSON.
+fn save_json(path: &Path, root: &Mutex<Directory>, info: &ScanInfo) -> Result<(), SnapshotError> {
+    let snapshot = JsonSnapshot {
+        format: JSON_FORMAT.to_string(),
+        version: JSON_VERSION,
+        scanned_at: to_timestamp(info.scanned_at),
+        paths: info.paths.iter().map(|path| JsonName::new(path.as_os_str())).collect(),
+        options: JsonScanOptions {
+            threads: info.options.threads,
+            one_filesystem: info.options.one_filesystem,
+            symlinks: symlinks_to_str(info.options.symlinks).to_string(),
+            mime_detection: mime_detection_to_str(info.options.mime_detection).to_string()
+        },
+        root: export_directory(&root.lock().unwrap(), None)
+    };
+
+    let writer = BufWriter::new(fs::File::create(path)?);
+    serde_json::to_writer(writer, &snapshot)?;
+    Ok(())
+}
+
+/// Reads a scan written by `save_json`, rebuilding the tree with its parent links.
+fn load_json(path: &Path) -> Result<(Arc<Mutex<Directory>>, ScanInfo), SnapshotError> {
+    let mut contents = String::new();
+    BufReader::new(fs::File::open(path)?).read_to_string(&mut contents)?;
+
+    let header: JsonHeader = serde_json::from_str(&contents)?;
+    if header.format.as_deref() != Some(JSON_FORMAT) {
+        return Err(SnapshotError::NotASnapshot);
+    }
+    match header.version {
+        Some(JSON_VERSION) => (),
+        Some(found) => return Err(SnapshotError::UnsupportedVersion { found, oldest: JSON_VERSION, newest: JSON_VERSION }),
+        None => return Err(SnapshotError::NotASnapshot)
+    }
+
+    // Directory trees easily nest deeper than serde_json's default limit.
+    let mut deserializer = serde_json::Deserializer::from_str(&contents);
+    deserializer.disable_recursion_limit();
+    let snapshot = JsonSnapshot::deserialize(&mut deserializer)?;
+
+    let info = ScanInfo {
+        paths: snapshot.paths.into_iter().map(|path| PathBuf::from(path.into_os_string())).collect(),
+        scanned_at: snapshot.scanned_at.map(from_timestamp).unwrap_or(UNIX_EPOCH),
+        options: ScanOptions {
+            threads: snapshot.options.threads,
+            one_filesystem: snapshot.options.one_filesystem,
+            symlinks: symlinks_from_str(&snapshot.options.symlinks),
+            mime_detection: mime_detection_from_str(&snapshot.options.mime_detection)
+        }
+    };
+    let root = import_directory(snapshot.root, Weak::new(), Path::new(""));
+    Ok((root, info))
+}
+
+#[cfg(unix)]
+fn os_str_to_bytes(name: &OsStr) -> Cow<'_, [u8]> {
+    use std::os::unix::ffi::OsStrExt;
+    Cow::Borrowed(name.as_bytes())
+}
+
+#[cfg(not(unix))]
+fn os_str_to_bytes(name: &OsStr) -> Cow<'_, [u8]> {
+    Cow::Owned(name.to_string_lossy().into_owned().into_bytes())
+}
+
+#[cfg(unix)]
+fn os_string_from_bytes(bytes: Vec<u8>) -> OsString {
+    use std::os::unix::ffi::OsStringExt;
+    OsString::from_vec(bytes)
+}
+
+#[cfg(not(unix))]
+fn os_string_from_bytes(bytes: Vec<u8>) -> OsString {
+    OsString::from(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+fn is_io_error(error: &&ReadError) -> bool {
+    matches!(error, ReadError::IOError { .. })
+}
+
+/// Writes the binary format. Numbers are LEB128 varints, so the typical small file costs a byte or two
+/// per field. Names, mime types and error kinds are interned: the first occurrence is written as 0 followed
+/// by the bytes, and every later one as its index + 1. Trees repeat the same names over and over, such as
+/// the files every package or repository has, so each of those costs a byte or two after the first.
+struct SnapshotWriter<W: Write> {
+    writer: W,
+    interned: HashMap<Vec<u8>, u64>
+}
+
+impl<W: Write> SnapshotWriter<W> {
+    fn new(writer: W) -> SnapshotWriter<W> {
+        SnapshotWriter {
+            writer,
+            interned: HashMap::new()
+        }
+    }
+
+    fn write_varint(&mut self, mut value: u64) -> io::Result<()> {
+        let mut buffer = [0u8; 10];
+        let mut length = 0;
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                buffer[length] = byte;
+                length += 1;
+                break;
+            }
+            buffer[length] = byte | 0x80;
+            length += 1;
+        }
+        self.writer.write_all(&buffer[..length])
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.write_varint(bytes.len() as u64)?;
+        self.writer.write_all(bytes)
+    }
+
+    fn write_interned(&mut self, value: &[u8]) -> io::Result<()> {
+        match self.interned.get(value) {
+            Some(id) => self.write_varint(id + 1),
+            None => {
+                self.interned.insert(value.to_vec(), self.interned.len() as u64);
+                self.write_varint(0)?;
+                self.write_bytes(value)
+            }
+        }
+    }
+
+    fn write_info(&mut self, info: &ScanInfo) -> io::Result<()> {
+        self.writer.write_all(BINARY_MAGIC)?;
+        self.write_varint(u64::from(BINARY_VERSION))?;
+        self.write_varint(to_timestamp(info.scanned_at).unwrap_or(0))?;
+        self.write_varint(info.paths.len() as u64)?;
+        for path in info.paths.iter() {
+            self.write_bytes(&os_str_to_bytes(path.as_os_str()))?;
+        }
+        self.write_varint(info.options.threads as u64)?;
+        self.write_varint(info.options.one_filesystem as u64)?;
+        self.write_bytes(symlinks_to_str(info.options.symlinks).as_bytes())?;
+        self.write_bytes(mime_detection_to_str(info.options.mime_detection).as_bytes())
+    }
+
+    fn write_error(&mut self, error: &ReadError) -> io::Result<()> {
+        if let ReadError::IOError { path, operation, message, .. } = error {
+            self.write_bytes(&os_str_to_bytes(path.as_os_str()))?;
+            self.write_interned(operation_to_str(*operation).as_bytes())?;
+            self.write_interned(category_to_str(error.get_category()).as_bytes())?;
+            self.write_bytes(message.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn write_file(&mut self, file: &File) -> io::Result<()> {
+        let modified = file.get_modified().and_then(to_timestamp);
+        let mut flags = 0;
+        if file.is_hard_link() {
+            flags |= FILE_HARD_LINK;
+        }
+        if modified.is_some() {
+            flags |= FILE_HAS_MODIFIED;
+        }
+        if file.get_link_target().is_some() {
+            flags |= FILE_HAS_LINK_TARGET;
+        }
+
+        self.write_interned(&os_str_to_bytes(file.get_os_name()))?;
+        self.write_interned(file.get_mime().as_bytes())?;
+        self.write_varint(flags)?;
+        self.write_varint(file.get_size())?;
+        self.write_varint(file.get_allocated_size())?;
+        if let Some(modified) = modified {
+            self.write_varint(modified)?;
+        }
+        if let Some(target) = file.get_link_target() {
+            self.write_bytes(&os_str_to_bytes(target.as_os_str()))?;
+        }
+        Ok(())
+    }
+
+    fn write_directory(&mut self, dir: &Directory, parent_path: Option<&Path>) -> io::Result<()> {
+        let derived_path = parent_path.map(|parent_path| parent_path.join(dir.get_os_name()));
+        let has_path = derived_path.as_deref() != Some(dir.get_path());
+        let modified = dir.get_modified().and_then(to_timestamp);
+        let error = dir.get_error().as_ref().filter(is_io_error);
+        let entry_errors: Vec<&ReadError> = dir.get_entry_errors().iter().filter(is_io_error).collect();
+
+        let mut flags = 0;
+        if has_path {
+            flags |= DIRECTORY_HAS_PATH;
+        }
+        if dir.is_mount_point() {
+            flags |= DIRECTORY_MOUNT_POINT;
+        }
+        if modified.is_some() {
+            flags |= DIRECTORY_HAS_MODIFIED;
+        }
+        if error.is_some() {
+            flags |= DIRECTORY_HAS_ERROR;
+        }
+        if dir.get_link_target().is_some() {
+            flags |= DIRECTORY_HAS_LINK_TARGET;
+        }
+
+        self.write_interned(&os_str_to_bytes(dir.get_os_name()))?;
+        self.write_varint(flags)?;
+        if has_path {
+            self.write_bytes(&os_str_to_bytes(dir.get_path().as_os_str()))?;
+        }
+        self.write_varint(dir.get_size())?;
+        self.write_varint(dir.get_allocated_size())?;
+        if let Some(modified) = modified {
+            self.write_varint(modified)?;
+        }
+        if let Some(error) = error {
+            self.write_error(error)?;
+        }
+        if let Some(target) = dir.get_link_target() {
+            self.write_bytes(&os_str_to_bytes(target.as_os_str()))?;
+        }
+
+        self.write_varint(entry_errors.len() as u64)?;
+        for error in entry_errors {
+            self.write_error(error)?;
+        }
+        self.write_varint(dir.get_files().len() as u64)?;
+        for file in dir.get_files() {
+            self.write_file(file)?;
+        }
+        self.write_varint(dir.get_subdirectories().len() as u64)?;
+        for subdir in dir.get_subdirectories() {
+            self.write_directory(&subdir.lock().unwrap(), Some(dir.get_path()))?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads what `SnapshotWriter` wrote, in the same order.
+struct SnapshotReader<R: Read> {
+    reader: R,
+    /// The version of the file being read, which decides how names are stored.
+    version: u64,
+    interned: Vec<Vec<u8>>
+}
+
+impl<R: Read> SnapshotReader<R> {
+    fn new(reader: R, version: u64) -> SnapshotReader<R> {
+        SnapshotReader {
+            reader,
+            version,
+            interned: Vec::new()
+        }
+    }
+
+    fn read_varint(&mut self) -> Result<u64, SnapshotError> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let mut byte = [0u8];
+            self.reader.read_exact(&mut byte)?;
+            value |= u64::from(byte[0] & 0x7f) << shift;
+            if byte[0] & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(SnapshotError::Corrupt)
+    }
+
+    fn read_bytes(&mut self) -> Result<Vec<u8>, SnapshotError> {
+        let length = self.read_varint()?;
+        // Read through take() rather than allocating `length` bytes up front, which a damaged file could make huge.
+        let mut bytes = Vec::new();
+        (&mut self.reader).take(length).read_to_end(&mut bytes)?;
+        if bytes.len() as u64 != length {
+            return Err(SnapshotError::Corrupt);
+        }
+        Ok(bytes)
+    }
+
+    fn read_text(&mut self) -> Result<String, SnapshotError> {
+        String::from_utf8(self.read_bytes()?).map_err(|_| SnapshotError::Corrupt)
+    }
+
+    fn read_interned(&mut self) -> Result<Vec<u8>, SnapshotError> {
+        match self.read_varint()? {
+            0 => {
+                let value = self.read_bytes()?;
+                self.interned.push(value.clone());
+                Ok(value)
+            },
+            id => self.interned.get((id - 1) as usize).cloned().ok_or(SnapshotError::Corrupt)
+        }
+    }
+
+    fn read_interned_text(&mut self) -> Result<String, SnapshotError> {
+        String::from_utf8(self.read_interned()?).map_err(|_| SnapshotError::Corrupt)
+    }
+
+    fn read_name(&mut self) -> Result<OsString, SnapshotError> {
+        let name = if self.version == 3 { self.read_bytes()? } else { self.read_interned()? };
+        Ok(os_string_from_bytes(name))
+    }
+
+    fn read_path(&mut self) -> Result<PathBuf, SnapshotError> {
+        Ok(PathBuf::from(os_string_from_bytes(self.read_bytes()?)))
+    }
+
+    fn read_info(&mut self) -> Result<ScanInfo, SnapshotError> {
+        let mut magic = [0u8; 8];
+        if self.reader.read_exact(&mut magic).is_err() || &magic != BINARY_MAGIC {
+            return Err(SnapshotError::NotASnapshot);
+        }
+        let version = self.read_varint()?;
+        if version < u64::from(OLDEST_BINARY_VERSION) || version > u64::from(BINARY_VERSION) {
+            return Err(SnapshotError::UnsupportedVersion { found: version as u32, oldest: OLDEST_BINARY_VERSION,
+                                                           newest: BINARY_VERSION });
+        }
+        self.version = version;
+
+        let scanned_at = from_timestamp(self.read_varint()?);
+        let path_count = self.read_varint()?;
+        let paths = (0..path_count).map(|_| self.read_path()).collect::<Result<Vec<_>, _>>()?;
+        let threads = self.read_varint()? as usize;
+        let one_filesystem = self.read_varint()? != 0;
+        let symlinks = symlinks_from_str(&self.read_text()?);
+        let mime_detection = if version >= 2 {
+            mime_detection_from_str(&self.read_text()?)
+        }
+        else {
+            MimeDetection::Extension
+        };
+        Ok(ScanInfo {
+            paths,
+            scanned_at,
+            options: ScanOptions { threads, one_filesystem, symlinks, mime_detection }
+        })
+    }
+
+    fn read_error(&mut self) -> Result<ReadError, SnapshotError> {
+        Ok(ReadError::IOError {
+            path: self.read_path()?,
+            operation: operation_from_str(&self.read_interned_text()?),
+            kind: category_to_kind(&self.read_interned_text()?),
+            message: self.read_text()?
+        })
+    }
+
+    fn read_file(&mut self) -> Result<File, SnapshotError> {
+        let name = self.read_name()?;
+        let mime = self.read_interned_text()?;
+        let flags = self.read_varint()?;
+        let size = self.read_varint()?;
+        let allocated_size = self.read_varint()?;
+
+        let mut file = File::new(&name, size, allocated_size, &mime, flags & FILE_HARD_LINK != 0);
+        if flags & FILE_HAS_MODIFIED != 0 {
+            file.set_modified(Some(from_timestamp(self.read_varint()?)));
+        }
+        if flags & FILE_HAS_LINK_TARGET != 0 {
+            file.set_link_target(Some(self.read_path()?));
+        }
+        Ok(file)
+    }
+
+    fn read_directory(&mut self, parent: Weak<Mutex<Directory>>, parent_path: &Path) -> Result<Arc<Mutex<Directory>>, SnapshotError> {
+        let name = self.read_name()?;
+        let flags = self.read_varint()?;
+        let path = if flags & DIRECTORY_HAS_PATH != 0 { self.read_path()? } else { parent_path.join(&name) };
+        let size = self.read_varint()?;
+        let allocated_size = self.read_varint()?;
+        let modified = if flags & DIRECTORY_HAS_MODIFIED != 0 { Some(from_timestamp(self.read_varint()?)) } else { None };
+        let error = if flags & DIRECTORY_HAS_ERROR != 0 { Some(self.read_error()?) } else { None };
+        let link_target = if flags & DIRECTORY_HAS_LINK_TARGET != 0 { Some(self.read_path()?) } else { None };
+
+        let entry_error_count = self.read_varint()?;
+        let entry_errors = (0..entry_error_count).map(|_| self.read_error()).collect::<Result<Vec<_>, _>>()?;
+        let file_count = self.read_varint()?;
+        let files = (0..file_count).map(|_| self.read_file()).collect::<Result<Vec<_>, _>>()?;
+
+        let directory = Arc::new(Mutex::new(Directory::new(&name, parent, &path)));
+        let subdir_count = self.read_varint()?;
+        let subdirectories = (0..subdir_count).map(|_| self.read_directory(Arc::downgrade(&directory), &path))
+                                              .collect::<Result<Vec<_>, _>>()?;
+
+        if let Ok(mut unwrapped_dir) = directory.lock() {
+            unwrapped_dir.set_size(size);
+            unwrapped_dir.set_allocated_size(allocated_size);
+            unwrapped_dir.set_modified(modified);
+            unwrapped_dir.set_error(error);
+            unwrapped_dir.set_entry_errors(entry_errors);
+            unwrapped_dir.set_mount_point(flags & DIRECTORY_MOUNT_POINT != 0);
+            unwrapped_dir.set_link_target(link_target);
+            unwrapped_dir.set_subdirectories(subdirectories);
+            unwrapped_dir.set_files(files);
+        }
+        Ok(directory)
+    }
+}
+
+/// Writes a finished scan to `path` in the binary format. The header holding `info` is left
+/// uncompressed so it can be checked without inflating the tree that follows it.
+fn save_binary(path: &Path, root: &Mutex<Directory>, info: &ScanInfo) -> Result<(), SnapshotError> {
+    let mut header = SnapshotWriter::new(BufWriter::new(fs::File::create(path)?));
+    header.write_info(info)?;
+
+    // The encoder does real work on every write, so the many tiny writes are batched up in front of it.
+    let mut body = SnapshotWriter::new(BufWriter::new(ZlibEncoder::new(header.writer, Compression::fast())));
+    body.write_directory(&root.lock().unwrap(), None)?;
+    body.writer.into_inner().map_err(|e| e.into_error())?.finish()?.flush()?;
+    Ok(())
+}
+
+fn read_binary(path: &Path) -> Result<(Arc<Mutex<Directory>>, ScanInfo), SnapshotError> {
+    let mut header = SnapshotReader::new(BufReader::new(fs::File::open(path)?), u64::from(BINARY_VERSION));
+    let info = header.read_info()?;
+
+    let mut body = SnapshotReader::new(BufReader::new(ZlibDecoder::new(header.reader)), header.version);
+    let root = body.read_directory(Weak::new(), Path::new(""))?;
+    Ok((root, info))
+}
+
+/// Reads a scan written by `save_binary`.
+fn load_binary(path: &Path) -> Result<(Arc<Mutex<Directory>>, ScanInfo), SnapshotError> {
+    read_binary(path).map_err(|error| match error {
+        SnapshotError::IOError(e) if e.kind() == io::ErrorKind::UnexpectedEof || e.kind() == io::ErrorKind::InvalidInput => {
+            SnapshotError::Corrupt
+        },
+        error => error
+    })
+}
+
+/// Writes a finished scan to `path`: as JSON if the file name ends in .json, in the compact binary format otherwise.
+pub fn save(path: &Path, root: &Mutex<Directory>, info: &ScanInfo) -> Result<(), SnapshotError> {
+    if matches!(path.extension(), Some(extension) if extension.eq_ignore_ascii_case("json")) {
+        save_json(path, root, info)
+    }
+    else {
+        save_binary(path, root, info)
+    }
+}
+
+/// Reads a scan saved by `save` in either format. Binary files are recognised by their magic bytes.
+pub fn load(path: &Path) -> Result<(Arc<Mutex<Directory>>, ScanInfo), SnapshotError> {
+    let mut magic = [0u8; 8];
+    let is_binary = fs::File::open(path)?.read_exact(&mut magic).is_ok() && &magic == BINARY_MAGIC;
+    if is_binary {
+        load_binary(path)
+    }
+    else {
+        load_json(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("disk_analyzer-{}-{}", std::process::id(), name))
+    }
+
+    #[cfg(unix)]
+    fn get_non_utf8_name() -> OsString {
+        os_string_from_bytes(b"caf\xe9.txt".to_vec())
+    }
+
+    #[cfg(not(unix))]
+    fn get_non_utf8_name() -> OsString {
+        OsString::from("café.txt")
+    }
+
+    fn new_error(path: &str, operation: Operation, kind: io::ErrorKind) -> ReadError {
+        ReadError::IOError { path: PathBuf::from(path), operation, kind, message: format!("{:?}", kind) }
+    }
+
+    /// /scan holding a file, a mount point and a directory that couldn't be read, and a subdirectory
+    /// with a non-UTF-8 file name, a symlink and an entry that couldn't be read.
+    fn create_tree() -> Arc<Mutex<Directory>> {
+        let root = Arc::new(Mutex::new(Directory::new(OsStr::new("scan"), Weak::new(), Path::new("/scan"))));
+        let sub = Arc::new(Mutex::new(Directory::new(OsStr::new("sub"), Arc::downgrade(&root), Path::new("/scan/sub"))));
+        let mount = Arc::new(Mutex::new(Directory::new(OsStr::new("mnt"), Arc::downgrade(&root), Path::new("/scan/mnt"))));
+        let locked = Arc::new(Mutex::new(Directory::new(OsStr::new("locked"), Arc::downgrade(&root), Path::new("/scan/locked"))));
+
+        {
+            let mut sub = sub.lock().unwrap();
+            let mut text = File::new(&get_non_utf8_name(), 300, 4096, "text/plain", false);
+            text.set_modified(Some(from_timestamp(1_500_000_000)));
+            let mut link = File::new(OsStr::new("link"), 0, 0, "inode/symlink", false);
+            link.set_link_target(Some(PathBuf::from("../a.png")));
+            sub.set_files(vec![text, link, File::new(OsStr::new("copy.png"), 0, 0, "image/png", true)]);
+            sub.set_entry_errors(vec![new_error("/scan/sub/gone", Operation::Metadata, io::ErrorKind::NotFound)]);
+            sub.set_size(300);
+            sub.set_allocated_size(4096);
+        }
+        mount.lock().unwrap().set_mount_point(true);
+        locked.lock().unwrap().set_error(Some(new_error("/scan/locked", Operation::OpenDirectory, io::ErrorKind::PermissionDenied)));
+
+        {
+            let mut unwrapped_root = root.lock().unwrap();
+            unwrapped_root.set_files(vec![File::new(OsStr::new("a.png"), 128, 4096, "image/png", false)]);
+            unwrapped_root.set_subdirectories(vec![sub, mount, locked]);
+            unwrapped_root.set_size(428);
+            unwrapped_root.set_allocated_size(8192);
+            unwrapped_root.set_modified(Some(from_timestamp(1_600_000_000)));
+        }
+        root
+    }
+
+    fn create_info() -> ScanInfo {
+        ScanInfo {
+            paths: vec![PathBuf::from("/scan")],
+            scanned_at: from_timestamp(1_700_000_000),
+            options: ScanOptions {
+                threads: 3,
+                one_filesystem: true,
+                symlinks: SymlinkPolicy::Follow,
+                mime_detection: MimeDetection::SniffUnknown
+            }
+        }
+    }
+
+    fn describe_errors<'a>(errors: impl IntoIterator<Item = &'a ReadError>) -> Vec<String> {
+        errors.into_iter().map(|error| format!("{} {:?}", error, error.get_category())).collect()
+    }
+
+    fn assert_same_files(expected: &[File], actual: &[File]) {
+        assert_eq!(expected.len(), actual.len());
+        for (expected, actual) in expected.iter().zip(actual) {
+            assert_eq!(expected.get_os_name(), actual.get_os_name());
+            assert_eq!(expected.get_size(), actual.get_size());
+            assert_eq!(expected.get_allocated_size(), actual.get_allocated_size());
+            assert_eq!(expected.get_mime(), actual.get_mime());
+            assert_eq!(expected.is_hard_link(), actual.is_hard_link());
+            assert_eq!(expected.get_link_target(), actual.get_link_target());
+            assert_eq!(expected.get_modified(), actual.get_modified());
+        }
+    }
+
+    /// Compares everything that's saved, and checks that each directory read back links to its parent.
+    fn assert_same_tree(expected: &Mutex<Directory>, actual: &Arc<Mutex<Directory>>) {
+        let expected = expected.lock().unwrap();
+        let unwrapped_actual = actual.lock().unwrap();
+        assert_eq!(expected.get_os_name(), unwrapped_actual.get_os_name());
+        assert_eq!(expected.get_path(), unwrapped_actual.get_path());
+        assert_eq!(expected.get_size(), unwrapped_actual.get_size());
+        assert_eq!(expected.get_allocated_size(), unwrapped_actual.get_allocated_size());
+        assert_eq!(expected.get_modified(), unwrapped_actual.get_modified());
+        assert_eq!(expected.is_mount_point(), unwrapped_actual.is_mount_point());
+        assert_eq!(expected.get_link_target(), unwrapped_actual.get_link_target());
+        assert_eq!(describe_errors(expected.get_error()), describe_errors(unwrapped_actual.get_error()));
+        assert_eq!(describe_errors(expected.get_entry_errors()), describe_errors(unwrapped_actual.get_entry_errors()));
+        assert_same_files(expected.get_files(), unwrapped_actual.get_files());
+
+        assert_eq!(expected.get_subdirectories().len(), unwrapped_actual.get_subdirectories().len());
+        for (expected_subdir, actual_subdir) in expected.get_subdirectories().iter().zip(unwrapped_actual.get_subdirectories()) {
+            let parent = actual_subdir.lock().unwrap().get_parent().upgrade().expect("Parent link wasn't rebuilt");
+            assert!(Arc::ptr_eq(&parent, actual));
+            assert_same_tree(expected_subdir, actual_subdir);
+        }
+    }
+
+    fn assert_same_info(expected: &ScanInfo, actual: &ScanInfo) {
+        assert_eq!(expected.paths, actual.paths);
+        assert_eq!(expected.scanned_at, actual.scanned_at);
+        assert_eq!(expected.options.threads, actual.options.threads);
+        assert_eq!(expected.options.one_filesystem, actual.options.one_filesystem);
+        assert_eq!(expected.options.symlinks, actual.options.symlinks);
+        assert_eq!(expected.options.mime_detection, actual.options.mime_detection);
+    }
+
+    fn round_trip(file_name: &str) {
+        let path = get_temp_path(file_name);
+        let root = create_tree();
+        let info = create_info();
+        save(&path, &root, &info).unwrap();
+        let loaded = load(&path);
+        fs::remove_file(&path).unwrap();
+
+        let (loaded_root, loaded_info) = loaded.unwrap();
+        assert!(loaded_root.lock().unwrap().get_parent().upgrade().is_none());
+        assert_same_tree(&root, &loaded_root);
+        assert_same_info(&info, &loaded_info);
+    }
+
+    /// Saves a scan, lets `damage` change the bytes on disk and loads it again.
+    fn load_damaged(file_name: &str, damage: impl FnOnce(&mut Vec<u8>)) -> Result<(Arc<Mutex<Directory>>, ScanInfo), SnapshotError> {
+        let path = get_temp_path(file_name);
+        save(&path, &create_tree(), &create_info()).unwrap();
+        let mut contents = fs::read(&path).unwrap();
+        damage(&mut contents);
+        fs::write(&path, &contents).unwrap();
+        let result = load(&path);
+        fs::remove_file(&path).unwrap();
+        result
+    }
+
+    #[test]
+    fn json_round_trip() {
+        round_trip("round-trip.json");
+    }
+
+    #[test]
+    fn json_without_version_is_not_a_snapshot() {
+        let path = get_temp_path("no-version.json");
+        fs::write(&path, r#"{"format": "disk_analyzer-scan"}"#).unwrap();
+        let result = load(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(SnapshotError::NotASnapshot)));
+    }
+
+    #[test]
+    fn json_from_newer_version_is_rejected() {
+        let path = get_temp_path("newer.json");
+        fs::write(&path, r#"{"format": "disk_analyzer-scan", "version": 2}"#).unwrap();
+        let result = load(&path);
+        fs::remove_file(&path).unwrap();
+        match result {
+            Err(error @ SnapshotError::UnsupportedVersion { found: 2, .. }) => {
+                assert_eq!(error.to_string(), "The scan was saved in format version 2, but only version 1 can be read");
+            },
+            _ => panic!("Expected an unsupported version")
+        }
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        round_trip("round-trip.diskscan");
+    }
+
+    /// A tree like a folder of checkouts: many directories holding files with the same few names.
+    fn create_repetitive_tree() -> Arc<Mutex<Directory>> {
+        let root = Arc::new(Mutex::new(Directory::new(OsStr::new("projects"), Weak::new(), Path::new("/projects"))));
+        let names = ["README.md", "LICENSE", ".gitignore", "Cargo.toml", "Cargo.lock", "main.rs", "lib.rs"];
+        let subdirectories = (0..2000).map(|index| {
+            let name = format!("project-{}", index);
+            let path = Path::new("/projects").join(&name);
+            let subdir = Arc::new(Mutex::new(Directory::new(OsStr::new(&name), Arc::downgrade(&root), &path)));
+            let files = names.iter().enumerate()
+                             .map(|(size, name)| File::new(OsStr::new(name), size as u64 * 1000, 4096, "text/plain", false))
+                             .collect();
+            subdir.lock().unwrap().set_files(files);
+            subdir
+        }).collect();
+        root.lock().unwrap().set_subdirectories(subdirectories);
+        root
+    }
+
+    #[test]
+    fn binary_is_much_smaller_than_json() {
+        let root = create_repetitive_tree();
+        let json_path = get_temp_path("size.json");
+        let binary_path = get_temp_path("size.diskscan");
+        save(&json_path, &root, &create_info()).unwrap();
+        save(&binary_path, &root, &create_info()).unwrap();
+        let json_size = fs::metadata(&json_path).unwrap().len();
+        let binary_size = fs::metadata(&binary_path).unwrap().len();
+        fs::remove_file(&json_path).unwrap();
+        fs::remove_file(&binary_path).unwrap();
+        assert!(binary_size * 10 < json_size, "{} bytes as binary, {} as JSON", binary_size, json_size);
+    }
+
+    #[test]
+    fn repeated_names_are_written_once() {
+        let file = File::new(OsStr::new("a-rather-long-file-name.txt"), 10, 4096, "text/plain", false);
+        let mut writer = SnapshotWriter::new(Vec::new());
+        writer.write_file(&file).unwrap();
+        let first_length = writer.writer.len();
+        writer.write_file(&file).unwrap();
+        // The indices of the name and the mime type, the flags and the two sizes.
+        assert_eq!(writer.writer.len() - first_length, 6);
+    }
+
+    #[test]
+    fn varints_round_trip() {
+        let values = [0, 1, 127, 128, 300, 16_383, 16_384, u64::from(u32::MAX), u64::MAX];
+        let mut writer = SnapshotWriter::new(Vec::new());
+        for value in values.iter() {
+            writer.write_varint(*value).unwrap();
+        }
+        assert_eq!(&writer.writer[..6], &[0x00, 0x01, 0x7f, 0x80, 0x01, 0xac]);
+        assert_eq!(writer.writer.len(), 1 + 1 + 1 + 2 + 2 + 2 + 3 + 5 + 10);
+
+        let mut reader = SnapshotReader::new(writer.writer.as_slice(), u64::from(BINARY_VERSION));
+        for value in values.iter() {
+            assert_eq!(reader.read_varint().unwrap(), *value);
+        }
+    }
+
+    #[test]
+    fn overlong_varint_is_corrupt() {
+        let bytes = [0xff; 11];
+        let mut reader = SnapshotReader::new(&bytes[..], u64::from(BINARY_VERSION));
+        assert!(matches!(reader.read_varint(), Err(SnapshotError::Corrupt)));
+    }
+
+    #[test]
+    fn interned_values_are_written_once() {
+        let mut writer = SnapshotWriter::new(Vec::new());
+        writer.write_interned(b"text/plain").unwrap();
+        writer.write_interned(b"image/png").unwrap();
+        writer.write_interned(b"text/plain").unwrap();
+        let text_length = 2 + b"text/plain".len();
+        let png_length = 2 + b"image/png".len();
+        assert_eq!(writer.writer.len(), text_length + png_length + 1);
+        assert_eq!(writer.writer[text_length + png_length], 1);
+
+        let mut reader = SnapshotReader::new(writer.writer.as_slice(), u64::from(BINARY_VERSION));
+        assert_eq!(reader.read_interned_text().unwrap(), "text/plain");
+        assert_eq!(reader.read_interned_text().unwrap(), "image/png");
+        assert_eq!(reader.read_interned_text().unwrap(), "text/plain");
+    }
+
+    #[test]
+    fn unknown_interned_value_is_corrupt() {
+        let bytes = [5];
+        let mut reader = SnapshotReader::new(&bytes[..], u64::from(BINARY_VERSION));
+        assert!(matches!(reader.read_interned(), Err(SnapshotError::Corrupt)));
+    }
+
+    /// Writes a scan of /old the way versions 1 to 3 did. Version 3 wrote file and directory names out in full.
+    fn write_old_binary(path: &Path, version: u64) {
+        fn write_name(body: &mut SnapshotWriter<impl Write>, version: u64, name: &[u8]) {
+            if version == 3 {
+                body.write_bytes(name).unwrap();
+            }
+            else {
+                body.write_interned(name).unwrap();
+            }
+        }
+
+        let mut header = SnapshotWriter::new(fs::File::create(path).unwrap());
+        header.writer.write_all(BINARY_MAGIC).unwrap();
+        header.write_varint(version).unwrap();
+        header.write_varint(1_700_000_000).unwrap();
+        header.write_varint(1).unwrap();
+        header.write_bytes(b"/old").unwrap();
+        header.write_varint(2).unwrap();
+        header.write_varint(0).unwrap();
+        header.write_bytes(b"ignore").unwrap();
+        if version >= 2 {
+            header.write_bytes(b"sniff_all").unwrap();
+        }
+
+        let mut body = SnapshotWriter::new(ZlibEncoder::new(header.writer, Compression::fast()));
+        write_name(&mut body, version, b"old");
+        body.write_varint(DIRECTORY_HAS_PATH).unwrap();
+        body.write_bytes(b"/old").unwrap();
+        body.write_varint(20).unwrap();
+        body.write_varint(8192).unwrap();
+        body.write_varint(0).unwrap();
+        body.write_varint(1).unwrap();
+        write_name(&mut body, version, b"a.txt");
+        body.write_interned(b"text/plain").unwrap();
+        body.write_varint(0).unwrap();
+        body.write_varint(10).unwrap();
+        body.write_varint(4096).unwrap();
+        body.write_varint(1).unwrap();
+        write_name(&mut body, version, b"sub");
+        body.write_varint(0).unwrap();
+        body.write_varint(10).unwrap();
+        body.write_varint(4096).unwrap();
+        body.write_varint(0).unwrap();
+        body.write_varint(1).unwrap();
+        // The same name and mime type as the file above, so only their indices are written, apart from the name
+        // in version 3.
+        write_name(&mut body, version, b"a.txt");
+        body.write_interned(b"text/plain").unwrap();
+        body.write_varint(0).unwrap();
+        body.write_varint(10).unwrap();
+        body.write_varint(4096).unwrap();
+        body.write_varint(0).unwrap();
+        body.writer.finish().unwrap();
+    }
+
+    fn load_old_binary(version: u64) -> (Arc<Mutex<Directory>>, ScanInfo) {
+        let path = get_temp_path(&format!("version-{}.diskscan", version));
+        write_old_binary(&path, version);
+        let result = load(&path);
+        fs::remove_file(&path).unwrap();
+        result.unwrap()
+    }
+
+    fn assert_old_tree(root: &Arc<Mutex<Directory>>) {
+        let root = root.lock().unwrap();
+        assert_eq!(root.get_path(), Path::new("/old"));
+        assert_eq!(root.get_size(), 20);
+        assert_eq!(root.get_files()[0].get_os_name(), "a.txt");
+        let sub = root.get_subdirectories()[0].lock().unwrap();
+        assert_eq!(sub.get_path(), Path::new("/old/sub"));
+        assert_eq!(sub.get_files()[0].get_os_name(), "a.txt");
+        assert_eq!(sub.get_files()[0].get_mime(), "text/plain");
+    }
+
+    #[test]
+    fn binary_version_1_is_read() {
+        let (root, info) = load_old_binary(1);
+        assert_old_tree(&root);
+        assert_eq!(info.options.symlinks, SymlinkPolicy::Ignore);
+        assert_eq!(info.options.mime_detection, MimeDetection::Extension);
+    }
+
+    #[test]
+    fn binary_version_2_is_read() {
+        let (root, info) = load_old_binary(2);
+        assert_old_tree(&root);
+        assert_eq!(info.options.mime_detection, MimeDetection::SniffAll);
+    }
+
+    #[test]
+    fn binary_version_3_is_read() {
+        let (root, info) = load_old_binary(3);
+        assert_old_tree(&root);
+        assert_eq!(info.options.mime_detection, MimeDetection::SniffAll);
+    }
+
+    #[test]
+    fn binary_from_newer_version_is_rejected() {
+        let result = load_damaged("newer.diskscan", |contents| contents[BINARY_MAGIC.len()] = 5);
+        match result {
+            Err(error @ SnapshotError::UnsupportedVersion { found: 5, .. }) => {
+                assert_eq!(error.to_string(), "The scan was saved in format version 5, but only versions 1 to 4 can be read");
+            },
+            _ => panic!("Expected an unsupported version")
+        }
+    }
+
+    #[test]
+    fn truncated_binary_is_corrupt() {
+        let result = load_damaged("truncated.diskscan", |contents| contents.truncate(contents.len() / 2));
+        assert!(matches!(result, Err(SnapshotError::Corrupt)));
+        let result = load_damaged("truncated-header.diskscan", |contents| contents.truncate(BINARY_MAGIC.len() + 3));
+        assert!(matches!(result, Err(SnapshotError::Corrupt)));
+    }
+
+    #[test]
+    fn damaged_binary_is_corrupt() {
+        // Everything after the header is compressed, so scribbling over its end breaks the stream.
+        let result = load_damaged("damaged.diskscan", |contents| {
+            let length = contents.len();
+            for byte in contents[length - 16..].iter_mut() {
+                *byte ^= 0x5a;
+            }
+        });
+        assert!(matches!(result, Err(SnapshotError::Corrupt)));
+    }
+}
@@ -0,0 +1,118 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use super::dir_walker::{Directory, SizeMode};
+use super::largest::Largest;
+use super::list_format;
+
+/// A file or directory in one of the top lists.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct TopEntry {
+    pub path: PathBuf,
+    pub icon: String
+}
+
+/// The largest files and the largest directories without subdirectories anywhere in a tree, biggest first.
+#[derive(Default)]
+pub struct TopEntries {
+    pub files: Vec<(u64, TopEntry)>,
+    pub directories: Vec<(u64, TopEntry)>
+}
+
+/// Only `dir` itself is locked while its entries are read, so the UI updating sizes along a path
+/// waits for one directory at most rather than for the whole walk.
+fn add_entries(dir: &Mutex<Directory>, size_mode: SizeMode, files: &mut Largest<TopEntry>, directories: &mut Largest<TopEntry>) {
+    let subdirectories: Vec<Arc<Mutex<Directory>>> = {
+        let dir = dir.lock().unwrap();
+        add_own_entries(&dir, size_mode, files, directories);
+        dir.get_subdirectories().clone()
+    };
+    for subdir in &subdirectories {
+        add_entries(subdir, size_mode, files, directories);
+    }
+}
+
+fn add_own_entries(dir: &Directory, size_mode: SizeMode, files: &mut Largest<TopEntry>, directories: &mut Largest<TopEntry>) {
+    for file in dir.get_files() {
+        let size = file.get_size_for(size_mode);
+        if files.accepts(size) {
+            files.push(size, TopEntry { path: dir.get_file_path(file), icon: list_format::get_file_icon(file).to_string() });
+        }
+    }
+
+    if dir.get_subdirectories().is_empty() {
+        let size = dir.get_size_for(size_mode);
+        if directories.accepts(size) {
+            let icon = list_format::get_directory_icon(dir).to_string();
+            directories.push(size, TopEntry { path: dir.get_path().to_path_buf(), icon });
+        }
+    }
+}
+
+/// Walks the tree below `root` once, keeping the `count` largest files and leaf directories.
+pub fn find_top_entries(root: &Mutex<Directory>, count: usize, size_mode: SizeMode) -> TopEntries {
+    let mut files = Largest::new(count);
+    let mut directories = Largest::new(count);
+    add_entries(root, size_mode, &mut files, &mut directories);
+    TopEntries { files: files.into_sorted_vec(), directories: directories.into_sorted_vec() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::Path;
+    use super::super::dir_walker::{self, ScanOptions, ScanProgress};
+
+    /// Scans a new directory called `name` holding `files`, given as paths below it and their sizes.
+    fn scan_temp_tree(name: &str, files: &[(&str, usize)]) -> (PathBuf, Arc<Mutex<Directory>>) {
+        let root = std::env::temp_dir().join(format!("disk_analyzer-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        for (path, size) in files {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, vec![0u8; *size]).unwrap();
+        }
+        let (_cancel_sender, cancel_receiver) = std::sync::mpsc::channel();
+        let tree = dir_walker::read_dir(&root, cancel_receiver, &ScanOptions::default(), &ScanProgress::new());
+        fs::remove_dir_all(&root).unwrap();
+        (root, tree)
+    }
+
+    fn get_relative_paths(root: &Path, entries: &[(u64, TopEntry)]) -> Vec<PathBuf> {
+        entries.iter().map(|(_, entry)| entry.path.strip_prefix(root).unwrap().to_path_buf()).collect()
+    }
+
+    #[test]
+    fn finds_the_largest_files_anywhere_in_the_tree() {
+        let (root, tree) = scan_temp_tree("top-files",
+            &[("small", 10), ("a/medium", 500), ("a/b/large", 1000), ("c/tiny", 1), ("c/d/e/big", 800)]);
+        let entries = find_top_entries(&tree, 3, SizeMode::Apparent);
+        assert_eq!(get_relative_paths(&root, &entries.files),
+                   vec![PathBuf::from("a/b/large"), PathBuf::from("c/d/e/big"), PathBuf::from("a/medium")]);
+        let sizes: Vec<u64> = entries.files.iter().map(|(size, _)| *size).collect();
+        assert_eq!(sizes, vec![1000, 800, 500]);
+    }
+
+    #[test]
+    fn only_lists_directories_without_subdirectories() {
+        let (root, tree) = scan_temp_tree("top-directories",
+            &[("parent/file", 5000), ("parent/leaf/file", 10), ("other/file", 2000)]);
+        let entries = find_top_entries(&tree, 10, SizeMode::Apparent);
+        assert_eq!(get_relative_paths(&root, &entries.directories), vec![PathBuf::from("other"), PathBuf::from("parent/leaf")]);
+        assert!(entries.directories.iter().all(|(_, entry)| entry.icon == "folder"));
+    }
+
+    #[test]
+    fn an_empty_tree_is_its_own_largest_directory() {
+        let (root, tree) = scan_temp_tree("top-empty", &[]);
+        let entries = find_top_entries(&tree, 10, SizeMode::Apparent);
+        assert!(entries.files.is_empty());
+        assert_eq!(entries.directories.len(), 1);
+        assert_eq!(entries.directories[0].1.path, root);
+    }
+}
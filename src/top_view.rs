@@ -0,0 +1,102 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use gtk::prelude::*;
+use std::cell::RefCell;
+use std::path::PathBuf;
+use super::list_format;
+use super::top_entries::{TopEntries, TopEntry};
+
+/// One list of entries with their full paths, biggest first.
+struct TopList {
+    container: gtk::Box,
+    tree: gtk::TreeView,
+    store: gtk::ListStore,
+    paths: RefCell<Vec<PathBuf>>
+}
+
+impl TopList {
+    fn new(title: &str) -> TopList {
+        // icon, path, size, index into paths
+        let store = gtk::ListStore::new(&[String::static_type(), String::static_type(), u64::static_type(), i64::static_type()]);
+        let tree = gtk::TreeView::with_model(&store);
+        list_format::add_icon_column(&tree, 0);
+        list_format::add_column(&tree, 1, "Path", None, false, gtk::CellRendererText::new());
+        list_format::add_size_column(&tree, 2, "Size");
+        let scrolled = gtk::ScrolledWindow::new::<gtk::Adjustment, gtk::Adjustment>(None, None);
+        scrolled.add(&tree);
+        scrolled.set_vexpand(true);
+
+        let label = gtk::Label::new(Some(title));
+        label.set_halign(gtk::Align::Start);
+        let container = gtk::Box::new(gtk::Orientation::Vertical, 4);
+        container.add(&label);
+        container.add(&scrolled);
+
+        TopList { container, tree, store, paths: RefCell::new(Vec::new()) }
+    }
+
+    fn set_entries(&self, entries: &[(u64, TopEntry)]) {
+        self.store.clear();
+        for (index, (size, entry)) in entries.iter().enumerate() {
+            self.store.insert_with_values(None, &[0, 1, 2, 3], &[&entry.icon, &entry.path.to_string_lossy().as_ref(), size,
+                                                                &(index as i64)]);
+        }
+        *self.paths.borrow_mut() = entries.iter().map(|(_, entry)| entry.path.clone()).collect();
+    }
+
+    fn get_path(&self, path: &gtk::TreePath) -> Option<PathBuf> {
+        let iter = self.store.get_iter(path)?;
+        let index = self.store.get_value(&iter, 3).get::<i64>()
+            .expect("Couldn't get index from tree model")
+            .expect("Couldn't get index from tree model");
+        self.paths.borrow().get(index as usize).cloned()
+    }
+}
+
+/// The largest files and the largest leaf directories anywhere below the root, side by side.
+pub struct TopView {
+    container: gtk::Paned,
+    files: TopList,
+    directories: TopList
+}
+
+impl TopView {
+    pub fn new() -> TopView {
+        let files = TopList::new("Largest files");
+        let directories = TopList::new("Largest directories without subdirectories");
+        let container = gtk::Paned::new(gtk::Orientation::Horizontal);
+        container.pack1(&files.container, true, false);
+        container.pack2(&directories.container, true, false);
+        container.set_position(400);
+        TopView { container, files, directories }
+    }
+
+    pub fn get_widget(&self) -> &gtk::Paned {
+        &self.container
+    }
+
+    pub fn get_file_list(&self) -> &gtk::TreeView {
+        &self.files.tree
+    }
+
+    pub fn get_directory_list(&self) -> &gtk::TreeView {
+        &self.directories.tree
+    }
+
+    pub fn set_entries(&self, entries: &TopEntries) {
+        self.files.set_entries(&entries.files);
+        self.directories.set_entries(&entries.directories);
+    }
+
+    /// The full path of the file in the row at `path`.
+    pub fn get_file_path(&self, path: &gtk::TreePath) -> Option<PathBuf> {
+        self.files.get_path(path)
+    }
+
+    /// The full path of the directory in the row at `path`.
+    pub fn get_directory_path(&self, path: &gtk::TreePath) -> Option<PathBuf> {
+        self.directories.get_path(path)
+    }
+}
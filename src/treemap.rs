@@ -0,0 +1,324 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use gtk::prelude::*;
+use humansize::{FileSize, file_size_opts as options};
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use super::dir_walker;
+use super::file_types;
+
+/// How many levels below the current directory are drawn inside their parents.
+const MAX_DEPTH: usize = 4;
+/// Tiles smaller than this in either dimension aren't drawn or subdivided any further.
+const MIN_TILE_SIZE: f64 = 3.0;
+/// Space kept around the children of a directory so its own outline stays visible.
+const PADDING: f64 = 2.0;
+/// Height of the strip at the top of a directory tile that holds its name, when there's room.
+const LABEL_HEIGHT: f64 = 14.0;
+
+#[derive(Clone, Copy, Debug)]
+struct Rect {
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64
+}
+
+impl Rect {
+    fn contains(&self, x: f64, y: f64) -> bool {
+        x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height
+    }
+}
+
+enum TileKind {
+    Directory(Arc<Mutex<dir_walker::Directory>>),
+    File(String)
+}
+
+struct Tile {
+    rect: Rect,
+    name: String,
+    path: String,
+    size: u64,
+    kind: TileKind,
+    /// 0 for entries of the directory being shown, 1 for their children and so on.
+    depth: usize
+}
+
+#[derive(Default)]
+struct TreemapState {
+    directory: Option<Arc<Mutex<dir_walker::Directory>>>,
+    size_mode: Option<dir_walker::SizeMode>,
+    /// Parents come before their children, so the last tile containing a point is the deepest one.
+    tiles: Vec<Tile>,
+    /// The allocation `tiles` was laid out for. None when the layout has to be redone.
+    layout_size: Option<(i32, i32)>,
+    hovered: Option<usize>
+}
+
+impl TreemapState {
+    fn get_tile_at(&self, x: f64, y: f64) -> Option<usize> {
+        self.tiles.iter().rposition(|tile| tile.rect.contains(x, y))
+    }
+}
+
+/// The worst aspect ratio of a row of `areas` laid out along a side of length `side`.
+fn get_worst_ratio(areas: &[f64], side: f64) -> f64 {
+    let sum: f64 = areas.iter().sum();
+    let max = areas.iter().cloned().fold(f64::MIN, f64::max);
+    let min = areas.iter().cloned().fold(f64::MAX, f64::min);
+    let side_squared = side * side;
+    let sum_squared = sum * sum;
+    f64::max(side_squared * max / sum_squared, sum_squared / (side_squared * min))
+}
+
+/// Lays `sizes`, which must be sorted largest first and non-zero, out in `rect` using the squarified
+/// algorithm of Bruls, Huizing and van Wijk. Returns one rectangle per size, in the same order.
+fn squarify(sizes: &[u64], rect: Rect) -> Vec<Rect> {
+    let total: u64 = sizes.iter().sum();
+    if total == 0 || rect.width <= 0.0 || rect.height <= 0.0 {
+        return Vec::new();
+    }
+
+    let scale = rect.width * rect.height / total as f64;
+    let areas: Vec<f64> = sizes.iter().map(|size| *size as f64 * scale).collect();
+    let mut result = Vec::with_capacity(areas.len());
+    let mut remaining = rect;
+    let mut start = 0;
+    while start < areas.len() {
+        // Keep adding tiles to the row for as long as that makes its worst tile more square.
+        let short_side = remaining.width.min(remaining.height);
+        let mut end = start + 1;
+        let mut worst = get_worst_ratio(&areas[start..end], short_side);
+        while end < areas.len() {
+            let next_worst = get_worst_ratio(&areas[start..=end], short_side);
+            if next_worst > worst {
+                break;
+            }
+            worst = next_worst;
+            end += 1;
+        }
+
+        let row_area: f64 = areas[start..end].iter().sum();
+        if remaining.width >= remaining.height {
+            let row_width = row_area / remaining.height;
+            let mut y = remaining.y;
+            for area in &areas[start..end] {
+                let height = area / row_width;
+                result.push(Rect { x: remaining.x, y, width: row_width, height });
+                y += height;
+            }
+            remaining.x += row_width;
+            remaining.width -= row_width;
+        }
+        else {
+            let row_height = row_area / remaining.width;
+            let mut x = remaining.x;
+            for area in &areas[start..end] {
+                let width = area / row_height;
+                result.push(Rect { x, y: remaining.y, width, height: row_height });
+                x += width;
+            }
+            remaining.y += row_height;
+            remaining.height -= row_height;
+        }
+        start = end;
+    }
+    result
+}
+
+fn layout_directory(dir: &Arc<Mutex<dir_walker::Directory>>, rect: Rect, depth: usize, size_mode: dir_walker::SizeMode,
+                    tiles: &mut Vec<Tile>) {
+    let mut children: Vec<(u64, Tile)> = Vec::new();
+    {
+        let unwrapped_dir = dir.lock().unwrap();
+        for subdir in unwrapped_dir.get_subdirectories() {
+            let unwrapped_subdir = subdir.lock().unwrap();
+            let size = unwrapped_subdir.get_size_for(size_mode);
+            children.push((size, Tile {
+                rect,
+                name: unwrapped_subdir.get_name().into_owned(),
+                path: unwrapped_subdir.get_display_path().into_owned(),
+                size,
+                kind: TileKind::Directory(subdir.clone()),
+                depth
+            }));
+        }
+        for file in unwrapped_dir.get_files() {
+            let size = file.get_size_for(size_mode);
+            children.push((size, Tile {
+                rect,
+                name: file.get_name().into_owned(),
+                path: unwrapped_dir.get_file_path(file).display().to_string(),
+                size,
+                kind: TileKind::File(file.get_mime().to_string()),
+                depth
+            }));
+        }
+    }
+    children.retain(|(size, _)| *size > 0);
+    children.sort_by_key(|(size, _)| std::cmp::Reverse(*size));
+
+    let sizes: Vec<u64> = children.iter().map(|(size, _)| *size).collect();
+    for ((_, mut tile), tile_rect) in children.into_iter().zip(squarify(&sizes, rect)) {
+        if tile_rect.width < MIN_TILE_SIZE || tile_rect.height < MIN_TILE_SIZE {
+            continue;
+        }
+        tile.rect = tile_rect;
+
+        let subdir = match &tile.kind {
+            TileKind::Directory(subdir) if depth < MAX_DEPTH => Some(subdir.clone()),
+            _ => None
+        };
+        tiles.push(tile);
+
+        if let Some(subdir) = subdir {
+            let label_height = if tile_rect.height > LABEL_HEIGHT * 3.0 { LABEL_HEIGHT } else { 0.0 };
+            let inner = Rect {
+                x: tile_rect.x + PADDING,
+                y: tile_rect.y + PADDING + label_height,
+                width: tile_rect.width - PADDING * 2.0,
+                height: tile_rect.height - PADDING * 2.0 - label_height
+            };
+            if inner.width >= MIN_TILE_SIZE && inner.height >= MIN_TILE_SIZE {
+                layout_directory(&subdir, inner, depth + 1, size_mode, tiles);
+            }
+        }
+    }
+}
+
+fn draw_label(cr: &cairo::Context, text: &str, rect: Rect, y: f64) {
+    if rect.width < 30.0 || rect.height < LABEL_HEIGHT {
+        return;
+    }
+    cr.save();
+    cr.rectangle(rect.x, rect.y, rect.width, rect.height);
+    cr.clip();
+    cr.set_source_rgb(0.1, 0.1, 0.1);
+    cr.move_to(rect.x + 3.0, y);
+    cr.show_text(text);
+    cr.restore();
+}
+
+fn draw(state: &TreemapState, cr: &cairo::Context) {
+    cr.select_font_face("Sans", cairo::FontSlant::Normal, cairo::FontWeight::Normal);
+    cr.set_font_size(10.0);
+    cr.set_line_width(1.0);
+
+    for (index, tile) in state.tiles.iter().enumerate() {
+        let rect = tile.rect;
+        let (red, green, blue) = match &tile.kind {
+            TileKind::Directory(_) => (0.85, 0.85, 0.85),
+            TileKind::File(mime) => file_types::Category::of(mime).get_color()
+        };
+        let highlight = if state.hovered == Some(index) { 0.15 } else { 0.0 };
+        cr.rectangle(rect.x, rect.y, rect.width, rect.height);
+        cr.set_source_rgb(red + highlight, green + highlight, blue + highlight);
+        cr.fill_preserve();
+        cr.set_source_rgb(0.3, 0.3, 0.3);
+        cr.stroke();
+
+        match tile.kind {
+            TileKind::Directory(_) => draw_label(cr, &tile.name, rect, rect.y + 11.0),
+            TileKind::File(_) if rect.height >= LABEL_HEIGHT * 2.0 => draw_label(cr, &tile.name, rect, rect.y + rect.height / 2.0 + 4.0),
+            TileKind::File(_) => ()
+        }
+    }
+}
+
+/// A squarified treemap of a directory and a few levels below it. Clicks are handled by the owner
+/// through `get_directory_at`, so the treemap never navigates on its own.
+pub struct Treemap {
+    drawing_area: gtk::DrawingArea,
+    state: Rc<RefCell<TreemapState>>
+}
+
+impl Treemap {
+    pub fn new() -> Treemap {
+        let drawing_area = gtk::DrawingArea::new();
+        drawing_area.set_size_request(200, 200);
+        drawing_area.set_has_tooltip(true);
+        drawing_area.add_events(gdk::EventMask::POINTER_MOTION_MASK | gdk::EventMask::BUTTON_PRESS_MASK
+                                | gdk::EventMask::LEAVE_NOTIFY_MASK);
+        let state = Rc::new(RefCell::new(TreemapState::default()));
+
+        let draw_state = state.clone();
+        drawing_area.connect_draw(move |area, cr| {
+            let mut state = draw_state.borrow_mut();
+            let size = (area.get_allocated_width(), area.get_allocated_height());
+            if state.layout_size != Some(size) {
+                state.tiles.clear();
+                state.hovered = None;
+                if let (Some(dir), Some(size_mode)) = (state.directory.clone(), state.size_mode) {
+                    let rect = Rect { x: 0.0, y: 0.0, width: f64::from(size.0), height: f64::from(size.1) };
+                    layout_directory(&dir, rect, 0, size_mode, &mut state.tiles);
+                }
+                state.layout_size = Some(size);
+            }
+            draw(&state, cr);
+            Inhibit(false)
+        });
+
+        let motion_state = state.clone();
+        drawing_area.connect_motion_notify_event(move |area, event| {
+            let (x, y) = event.get_position();
+            let mut state = motion_state.borrow_mut();
+            let hovered = state.get_tile_at(x, y);
+            if hovered != state.hovered {
+                state.hovered = hovered;
+                area.queue_draw();
+            }
+            Inhibit(false)
+        });
+
+        let leave_state = state.clone();
+        drawing_area.connect_leave_notify_event(move |area, _| {
+            leave_state.borrow_mut().hovered = None;
+            area.queue_draw();
+            Inhibit(false)
+        });
+
+        let tooltip_state = state.clone();
+        drawing_area.connect_query_tooltip(move |_, x, y, _, tooltip| {
+            let state = tooltip_state.borrow();
+            match state.get_tile_at(f64::from(x), f64::from(y)) {
+                Some(index) => {
+                    let tile = &state.tiles[index];
+                    tooltip.set_text(Some(&format!("{}\n{}", tile.path, tile.size.file_size(options::CONVENTIONAL).unwrap())));
+                    true
+                },
+                None => false
+            }
+        });
+
+        Treemap { drawing_area, state }
+    }
+
+    pub fn get_widget(&self) -> &gtk::DrawingArea {
+        &self.drawing_area
+    }
+
+    /// Shows `dir`. Also called when the size mode changes, since that changes every tile.
+    pub fn set_directory(&self, dir: &Arc<Mutex<dir_walker::Directory>>, size_mode: dir_walker::SizeMode) {
+        let mut state = self.state.borrow_mut();
+        state.directory = Some(dir.clone());
+        state.size_mode = Some(size_mode);
+        state.layout_size = None;
+        self.drawing_area.queue_draw();
+    }
+
+    /// The subdirectory of the shown directory whose tile contains (x, y), if any. Clicking anywhere inside
+    /// it, including on the tiles nested in it, opens that one level down.
+    pub fn get_directory_at(&self, x: f64, y: f64) -> Option<Arc<Mutex<dir_walker::Directory>>> {
+        let state = self.state.borrow();
+        state.tiles.iter()
+             .filter(|tile| tile.depth == 0 && tile.rect.contains(x, y))
+             .find_map(|tile| match &tile.kind {
+                 TileKind::Directory(dir) => Some(dir.clone()),
+                 TileKind::File(_) => None
+             })
+    }
+}
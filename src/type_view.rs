@@ -0,0 +1,253 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use gtk::prelude::*;
+use humansize::{FileSize, file_size_opts as options};
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use super::dir_walker;
+use super::file_types::{self, Category, TypeTotals};
+use super::list_format;
+
+/// How many files are listed for the selected type.
+const LARGEST_FILE_COUNT: usize = 100;
+const BAR_HEIGHT: f64 = 20.0;
+const BAR_SPACING: f64 = 4.0;
+/// Room on the left of the bar chart for the category names.
+const LABEL_WIDTH: f64 = 150.0;
+
+#[derive(Default)]
+struct TypeViewState {
+    directory: Option<Arc<Mutex<dir_walker::Directory>>>,
+    size_mode: Option<dir_walker::SizeMode>,
+    /// Set when the directory or size mode changed while the view wasn't shown.
+    stale: bool,
+    categories: Vec<(Category, TypeTotals)>
+}
+
+/// The category shown on each row of the bar chart, if any.
+fn get_bar_at(state: &TypeViewState, y: f64) -> Option<Category> {
+    let index = (y / (BAR_HEIGHT + BAR_SPACING)) as usize;
+    state.categories.get(index).map(|(category, _)| *category)
+}
+
+fn draw(state: &TypeViewState, width: f64, cr: &cairo::Context) {
+    let largest = state.categories.first().map_or(0, |(_, totals)| totals.size).max(1);
+    let total: u64 = state.categories.iter().map(|(_, totals)| totals.size).sum();
+    cr.select_font_face("Sans", cairo::FontSlant::Normal, cairo::FontWeight::Normal);
+    cr.set_font_size(11.0);
+
+    for (index, (category, totals)) in state.categories.iter().enumerate() {
+        let y = index as f64 * (BAR_HEIGHT + BAR_SPACING);
+        let bar_width = ((width - LABEL_WIDTH - 4.0) * totals.size as f64 / largest as f64).max(1.0);
+        let (red, green, blue) = category.get_color();
+        cr.rectangle(LABEL_WIDTH, y, bar_width, BAR_HEIGHT);
+        cr.set_source_rgb(red, green, blue);
+        cr.fill();
+
+        cr.set_source_rgb(0.1, 0.1, 0.1);
+        cr.move_to(4.0, y + BAR_HEIGHT / 2.0 + 4.0);
+        cr.show_text(&category.to_string());
+        let percentage = if total == 0 { 0.0 } else { totals.size as f64 / total as f64 * 100.0 };
+        cr.move_to(LABEL_WIDTH + 4.0, y + BAR_HEIGHT / 2.0 + 4.0);
+        cr.show_text(&format!("{} ({:.0}%)", totals.size.file_size(options::CONVENTIONAL).unwrap(), percentage));
+    }
+}
+
+fn fill_type_store(store: &gtk::TreeStore, breakdown: &file_types::TypeBreakdown) {
+    let columns = [0, 1, 2, 3, 4];
+    for (index, (category, totals)) in breakdown.categories.iter().enumerate() {
+        let category_iter = store.insert_with_values(None, None, &columns, &[&category.to_string(), &totals.size, &totals.count,
+                                                                             &(index as i64), &""]);
+        let mime_types = breakdown.mime_types.iter().filter(|(mime, _)| Category::of(mime) == *category);
+        for (mime, totals) in mime_types {
+            store.insert_with_values(Some(&category_iter), None, &columns, &[mime, &totals.size, &totals.count, &(index as i64), mime]);
+        }
+    }
+}
+
+/// How much space each type of file takes up below a directory, as a bar chart of categories and a list
+/// that expands into mime types. Selecting a category or type lists its largest files.
+pub struct TypeView {
+    container: gtk::Paned,
+    chart: gtk::DrawingArea,
+    type_tree: gtk::TreeView,
+    type_store: gtk::TreeStore,
+    file_store: gtk::ListStore,
+    file_title: gtk::Label,
+    state: Rc<RefCell<TypeViewState>>
+}
+
+impl TypeView {
+    pub fn new() -> Rc<TypeView> {
+        let chart = gtk::DrawingArea::new();
+        chart.add_events(gdk::EventMask::BUTTON_PRESS_MASK);
+
+        // name, size, file count, category index, mime type (empty for categories)
+        let type_store = gtk::TreeStore::new(&[String::static_type(), u64::static_type(), u64::static_type(), i64::static_type(),
+                                               String::static_type()]);
+        let type_tree = gtk::TreeView::with_model(&type_store);
+        list_format::add_column(&type_tree, 0, "Type", None, false, gtk::CellRendererText::new());
+        list_format::add_size_column(&type_tree, 1, "Size");
+        list_format::add_column(&type_tree, 2, "Files", None, false, gtk::CellRendererText::new());
+        let type_scrolled = gtk::ScrolledWindow::new::<gtk::Adjustment, gtk::Adjustment>(None, None);
+        type_scrolled.add(&type_tree);
+        type_scrolled.set_vexpand(true);
+
+        let type_box = gtk::Box::new(gtk::Orientation::Vertical, 4);
+        type_box.add(&chart);
+        type_box.add(&type_scrolled);
+
+        // mime type, path, size
+        let file_store = gtk::ListStore::new(&[String::static_type(), String::static_type(), u64::static_type()]);
+        let file_list = gtk::TreeView::with_model(&file_store);
+        list_format::add_icon_column(&file_list, 0);
+        list_format::add_column(&file_list, 1, "Path", None, false, gtk::CellRendererText::new());
+        list_format::add_size_column(&file_list, 2, "Size");
+        let file_scrolled = gtk::ScrolledWindow::new::<gtk::Adjustment, gtk::Adjustment>(None, None);
+        file_scrolled.add(&file_list);
+        file_scrolled.set_vexpand(true);
+        let file_title = gtk::Label::new(Some("Select a type to list its largest files"));
+        file_title.set_halign(gtk::Align::Start);
+
+        let file_box = gtk::Box::new(gtk::Orientation::Vertical, 4);
+        file_box.add(&file_title);
+        file_box.add(&file_scrolled);
+
+        let container = gtk::Paned::new(gtk::Orientation::Horizontal);
+        container.pack1(&type_box, true, false);
+        container.pack2(&file_box, true, false);
+        container.set_position(400);
+
+        let view = Rc::new(TypeView {
+            container,
+            chart,
+            type_tree,
+            type_store,
+            file_store,
+            file_title,
+            state: Rc::new(RefCell::new(TypeViewState::default()))
+        });
+
+        let draw_state = view.state.clone();
+        view.chart.connect_draw(move |area, cr| {
+            draw(&draw_state.borrow(), f64::from(area.get_allocated_width()), cr);
+            Inhibit(false)
+        });
+
+        // Clicking a bar selects the category in the list, which lists its files.
+        let click_view = Rc::downgrade(&view);
+        view.chart.connect_button_press_event(move |_, event| {
+            if let Some(view) = click_view.upgrade() {
+                let category = get_bar_at(&view.state.borrow(), event.get_position().1);
+                if let Some(category) = category {
+                    view.select_category(category);
+                }
+            }
+            Inhibit(false)
+        });
+
+        let selection_view = Rc::downgrade(&view);
+        view.type_tree.get_selection().connect_changed(move |selection| {
+            if let (Some(view), Some((model, iter))) = (selection_view.upgrade(), selection.get_selected()) {
+                let index = model.get_value(&iter, 3).get::<i64>()
+                    .expect("Couldn't get category from tree model")
+                    .expect("Couldn't get category from tree model");
+                let mime = model.get_value(&iter, 4).get::<String>()
+                    .expect("Couldn't get mime type from tree model")
+                    .unwrap_or_default();
+                view.list_largest_files(index as usize, &mime);
+            }
+        });
+
+        // Adding everything up can take a moment on big trees, so it waits until the view is shown.
+        let map_view = Rc::downgrade(&view);
+        view.container.connect_map(move |_| {
+            if let Some(view) = map_view.upgrade() {
+                if view.state.borrow().stale {
+                    view.refresh();
+                }
+            }
+        });
+
+        view
+    }
+
+    pub fn get_widget(&self) -> &gtk::Paned {
+        &self.container
+    }
+
+    /// Shows the types of the files below `dir`.
+    pub fn set_directory(&self, dir: &Arc<Mutex<dir_walker::Directory>>, size_mode: dir_walker::SizeMode) {
+        {
+            let mut state = self.state.borrow_mut();
+            state.directory = Some(dir.clone());
+            state.size_mode = Some(size_mode);
+            state.stale = true;
+        }
+        if self.container.get_mapped() {
+            self.refresh();
+        }
+    }
+
+    fn refresh(&self) {
+        let (dir, size_mode) = {
+            let state = self.state.borrow();
+            match (&state.directory, state.size_mode) {
+                (Some(dir), Some(size_mode)) => (dir.clone(), size_mode),
+                _ => return
+            }
+        };
+
+        let breakdown = file_types::get_breakdown(&dir, size_mode);
+        {
+            let mut state = self.state.borrow_mut();
+            state.stale = false;
+            state.categories = breakdown.categories.clone();
+        }
+        self.type_store.clear();
+        fill_type_store(&self.type_store, &breakdown);
+        self.file_store.clear();
+        self.file_title.set_text("Select a type to list its largest files");
+
+        let height = breakdown.categories.len() as f64 * (BAR_HEIGHT + BAR_SPACING);
+        self.chart.set_size_request(-1, height as i32);
+        self.chart.queue_draw();
+    }
+
+    fn select_category(&self, category: Category) {
+        let index = self.state.borrow().categories.iter().position(|(other, _)| *other == category);
+        if let Some(index) = index {
+            let path = gtk::TreePath::from_indicesv(&[index as i32]);
+            self.type_tree.get_selection().select_path(&path);
+            self.type_tree.scroll_to_cell(Some(&path), None::<&gtk::TreeViewColumn>, false, 0.0, 0.0);
+        }
+    }
+
+    /// Lists the largest files of the category at `index`, or only those of `mime` if it isn't empty.
+    fn list_largest_files(&self, index: usize, mime: &str) {
+        let (dir, size_mode, category) = {
+            let state = self.state.borrow();
+            match (&state.directory, state.size_mode, state.categories.get(index)) {
+                (Some(dir), Some(size_mode), Some((category, _))) => (dir.clone(), size_mode, *category),
+                _ => return
+            }
+        };
+
+        let files = if mime.is_empty() {
+            self.file_title.set_text(&format!("Largest files: {}", category));
+            file_types::get_largest_files(&dir, &|other| Category::of(other) == category, LARGEST_FILE_COUNT, size_mode)
+        }
+        else {
+            self.file_title.set_text(&format!("Largest files: {}", mime));
+            file_types::get_largest_files(&dir, &|other| other == mime, LARGEST_FILE_COUNT, size_mode)
+        };
+
+        self.file_store.clear();
+        for (size, (path, mime)) in files {
+            self.file_store.insert_with_values(None, &[0, 1, 2], &[&mime, &path.to_string_lossy().as_ref(), &size]);
+        }
+    }
+}
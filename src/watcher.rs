@@ -0,0 +1,262 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use inotify::{EventMask, Inotify, WatchDescriptor, WatchMask};
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::channel;
+use std::thread;
+use std::time::Duration;
+use super::dir_walker;
+
+/// How often the watcher thread looks for new events. Everything that happens in between is handled
+/// together, so a file that's being written to is only read once per interval.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A change to the tree, worked out on the watcher thread so that applying it is quick.
+pub enum WatchEvent {
+    /// A file in the directory at the path was created, moved in or changed size.
+    FileChanged(PathBuf, dir_walker::File),
+    FileRemoved(PathBuf, OsString),
+    /// A directory was created in or moved into the directory at the path, and has been scanned.
+    DirectoryAdded(PathBuf, Arc<Mutex<dir_walker::Directory>>),
+    DirectoryRemoved(PathBuf, OsString),
+    /// Something went wrong, such as running out of inotify watches. Watching carries on where it can.
+    Error(String)
+}
+
+impl WatchEvent {
+    /// The directory whose contents changed.
+    pub fn get_directory(&self) -> Option<&Path> {
+        match self {
+            WatchEvent::FileChanged(dir, _) | WatchEvent::FileRemoved(dir, _)
+                | WatchEvent::DirectoryAdded(dir, _) | WatchEvent::DirectoryRemoved(dir, _) => Some(dir),
+            WatchEvent::Error(_) => None
+        }
+    }
+}
+
+/// Patches the tree below `root` with `event`, including the sizes of the ancestors of whatever changed.
+/// Events for directories that aren't part of the tree are ignored.
+pub fn apply(root: &Arc<Mutex<dir_walker::Directory>>, event: WatchEvent) {
+    let dir = match event.get_directory().and_then(|path| dir_walker::find_directory(root, path)) {
+        Some(dir) => dir,
+        None => return
+    };
+    match event {
+        WatchEvent::FileChanged(_, file) => dir_walker::update_file(&dir, file),
+        WatchEvent::FileRemoved(_, name) => dir_walker::remove_file(&dir, &name),
+        WatchEvent::DirectoryAdded(_, subdir) => dir_walker::add_subdirectory(&dir, subdir),
+        WatchEvent::DirectoryRemoved(_, name) => dir_walker::remove_subdirectory(&dir, &name),
+        WatchEvent::Error(_) => ()
+    }
+}
+
+fn collect_watched_paths(dir: &Mutex<dir_walker::Directory>, paths: &mut Vec<PathBuf>) {
+    let unwrapped_dir = dir.lock().unwrap();
+    if unwrapped_dir.has_error() || unwrapped_dir.is_mount_point() {
+        return;
+    }
+
+    // The combined root of a scan of several paths has no path of its own.
+    let path = unwrapped_dir.get_path();
+    if !path.as_os_str().is_empty() {
+        paths.push(path.to_path_buf());
+    }
+    for subdir in unwrapped_dir.get_subdirectories() {
+        collect_watched_paths(subdir, paths);
+    }
+}
+
+struct WatchState {
+    inotify: Inotify,
+    directories: HashMap<WatchDescriptor, PathBuf>,
+    options: dir_walker::ScanOptions,
+    /// Running out of watches is only reported once, rather than for every directory that's left.
+    watch_failed: bool
+}
+
+impl WatchState {
+    /// Adds a watch for every directory below `dir` that was read. The paths are collected first, so the
+    /// tree isn't kept locked from the window while the watches are added.
+    fn watch_tree(&mut self, dir: &Mutex<dir_walker::Directory>, events: &mut Vec<WatchEvent>) {
+        let mut paths = Vec::new();
+        collect_watched_paths(dir, &mut paths);
+        for path in paths {
+            self.watch_directory(&path, events);
+        }
+    }
+
+    /// Adds a watch for the directory at `path` alone. Watching it again changes nothing.
+    fn watch_directory(&mut self, path: &Path, events: &mut Vec<WatchEvent>) {
+        let mask = WatchMask::CREATE | WatchMask::DELETE | WatchMask::MODIFY | WatchMask::CLOSE_WRITE
+                   | WatchMask::MOVED_FROM | WatchMask::MOVED_TO;
+        match self.inotify.add_watch(path, mask) {
+            Ok(wd) => {
+                self.directories.insert(wd, path.to_path_buf());
+            },
+            Err(e) if !self.watch_failed => {
+                self.watch_failed = true;
+                events.push(WatchEvent::Error(format!("Could not watch {} and possibly other directories\n\n{}",
+                                                      path.display(), e)));
+            },
+            Err(_) => ()
+        }
+    }
+
+    /// Stops watching `path` and everything below it, after it has been moved elsewhere.
+    fn unwatch(&mut self, path: &Path) {
+        let removed: Vec<WatchDescriptor> = self.directories.iter()
+                                                .filter(|(_, watched)| watched.starts_with(path))
+                                                .map(|(wd, _)| wd.clone())
+                                                .collect();
+        for wd in removed {
+            self.directories.remove(&wd);
+            // Fails if the directory is already gone, which removes the watch anyway.
+            let _ = self.inotify.rm_watch(wd);
+        }
+    }
+
+    fn read_directory(&self, path: &Path) -> Arc<Mutex<dir_walker::Directory>> {
+        let (_cancel_sender, cancel_receiver) = channel();
+        let progress = dir_walker::ScanProgress::new();
+        dir_walker::read_dir(&path.to_path_buf(), cancel_receiver, &self.options, &progress)
+    }
+
+    /// Reads and watches a directory that just appeared. It's watched before it's read, so nothing created
+    /// in it meanwhile is missed.
+    fn scan_directory(&mut self, path: &Path, events: &mut Vec<WatchEvent>) -> Arc<Mutex<dir_walker::Directory>> {
+        self.watch_directory(path, events);
+        let dir = self.read_directory(path);
+        if dir.lock().unwrap().get_subdirectories().is_empty() {
+            return dir;
+        }
+
+        // Its subdirectories could only be watched after they were read, like after `mkdir -p a/b && touch a/b/f`,
+        // so they're read once more now that they're watched.
+        self.watch_tree(&dir, events);
+        let dir = self.read_directory(path);
+        self.watch_tree(&dir, events);
+        dir
+    }
+
+    /// Turns one inotify event into changes to the tree. Files that changed are only collected in
+    /// `changed_files`, so they can be read once however many events they caused.
+    fn handle_event(&mut self, wd: &WatchDescriptor, mask: EventMask, name: Option<OsString>,
+                    changed_files: &mut HashSet<PathBuf>, events: &mut Vec<WatchEvent>) {
+        if mask.contains(EventMask::Q_OVERFLOW) {
+            events.push(WatchEvent::Error("Too many changes happened at once and some were missed. \
+                                           Rescan to bring the view up to date.".to_string()));
+            return;
+        }
+        if mask.contains(EventMask::IGNORED) {
+            self.directories.remove(wd);
+            return;
+        }
+
+        let (dir, name) = match (self.directories.get(wd), name) {
+            (Some(dir), Some(name)) => (dir.clone(), name),
+            _ => return
+        };
+        let path = dir.join(&name);
+        if mask.contains(EventMask::ISDIR) {
+            if mask.intersects(EventMask::CREATE | EventMask::MOVED_TO) {
+                let subdir = self.scan_directory(&path, events);
+                events.push(WatchEvent::DirectoryAdded(dir, subdir));
+            }
+            else if mask.intersects(EventMask::DELETE | EventMask::MOVED_FROM) {
+                self.unwatch(&path);
+                events.push(WatchEvent::DirectoryRemoved(dir, name));
+            }
+        }
+        else if mask.intersects(EventMask::DELETE | EventMask::MOVED_FROM) {
+            changed_files.remove(&path);
+            events.push(WatchEvent::FileRemoved(dir, name));
+        }
+        else {
+            changed_files.insert(path);
+        }
+    }
+
+    /// Handles everything that happened since the last call. Returns false if inotify stopped working.
+    fn read_events(&mut self, buffer: &mut [u8], events: &mut Vec<WatchEvent>) -> bool {
+        let mut changed_files = HashSet::new();
+        loop {
+            let raw_events: Vec<(WatchDescriptor, EventMask, Option<OsString>)> = match self.inotify.read_events(buffer) {
+                Ok(raw_events) => raw_events.map(|event| (event.wd, event.mask, event.name.map(|name| name.to_os_string())))
+                                            .collect(),
+                Err(e) => {
+                    events.push(WatchEvent::Error(format!("Stopped watching for changes\n\n{}", e)));
+                    return false;
+                }
+            };
+            if raw_events.is_empty() {
+                break;
+            }
+            for (wd, mask, name) in raw_events {
+                self.handle_event(&wd, mask, name, &mut changed_files, events);
+            }
+        }
+
+        for path in changed_files {
+            // Files that can't be read, most likely because they're already gone again, are left as they were.
+            if let (Some(dir), Ok(Some(file))) = (path.parent(), dir_walker::read_file(&path, &self.options)) {
+                events.push(WatchEvent::FileChanged(dir.to_path_buf(), file));
+            }
+        }
+        true
+    }
+}
+
+/// Watches every directory of a scanned tree for changes on a background thread, until it's dropped.
+/// Batches of changes are sent to `sender`, to be applied to the tree with `apply`.
+pub struct Watcher {
+    stopped: Arc<AtomicBool>
+}
+
+impl Watcher {
+    pub fn new(root: &Arc<Mutex<dir_walker::Directory>>, options: &dir_walker::ScanOptions,
+               sender: relm::Sender<Vec<WatchEvent>>) -> io::Result<Watcher> {
+        let mut state = WatchState {
+            inotify: Inotify::init()?,
+            directories: HashMap::new(),
+            // Directories that appear while watching are small, they're scanned on the watcher thread alone.
+            options: dir_walker::ScanOptions { threads: 1, ..options.clone() },
+            watch_failed: false
+        };
+        let stopped = Arc::new(AtomicBool::new(false));
+        let thread_stopped = stopped.clone();
+        let root = root.clone();
+
+        thread::spawn(move || {
+            let mut events = Vec::new();
+            state.watch_tree(&root, &mut events);
+            drop(root);
+
+            let mut buffer = [0; 4096];
+            while !thread_stopped.load(Ordering::Relaxed) {
+                let working = state.read_events(&mut buffer, &mut events);
+                if !events.is_empty() {
+                    sender.send(std::mem::take(&mut events)).expect("Couldn't send message");
+                }
+                if !working {
+                    break;
+                }
+                thread::sleep(POLL_INTERVAL);
+            }
+        });
+
+        Ok(Watcher { stopped })
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+}